@@ -0,0 +1,93 @@
+use crate::parser::Diagnostic;
+use std::{fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io { path: PathBuf, error: io::Error },
+    /// Part of the source couldn't be parsed. The path is only known when the source
+    /// was read from a file.
+    Parse {
+        path: Option<PathBuf>,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// The format of the file couldn't be detected from its extension or contents.
+    UnsupportedExtension(PathBuf),
+    /// No format has been registered with this name.
+    UnknownFormat(String),
+    /// The path doesn't name a file, so no output path can be made from it.
+    InvalidPath(PathBuf),
+    /// The output file already exists, and overwriting wasn't allowed.
+    OutputConflict(PathBuf),
+    /// The plural rule of the locale isn't known.
+    UnsupportedLocale(String),
+    /// A key selection pattern isn't a valid regular expression.
+    InvalidPattern {
+        pattern: String,
+        error: regex::Error,
+    },
+}
+
+impl Error {
+    /// Attach the path of the file that was being parsed.
+    pub fn with_path(self, path: impl Into<PathBuf>) -> Self {
+        match self {
+            Error::Parse {
+                path: None,
+                diagnostics,
+            } => Error::Parse {
+                path: Some(path.into()),
+                diagnostics,
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Error::Parse { path, diagnostics } => {
+                match path {
+                    Some(path) => write!(f, "Unable to parse all of {}", path.display())?,
+                    None => write!(f, "Unable to parse all of the source")?,
+                }
+                for diagnostic in diagnostics {
+                    write!(f, "\n  ")?;
+                    if let Some(path) = path {
+                        write!(f, "{}:", path.display())?;
+                    }
+                    write!(f, "{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            Error::UnsupportedExtension(path) => write!(
+                f,
+                "The file cannot be converted as its format could not be detected from its extension or contents: {}",
+                path.display()
+            ),
+            Error::UnknownFormat(name) => write!(f, "There is no format named \"{}\"", name),
+            Error::InvalidPath(path) => write!(f, "The path is not a file: {}", path.display()),
+            Error::OutputConflict(path) => {
+                write!(f, "The output file already exists: {}", path.display())
+            }
+            Error::UnsupportedLocale(locale) => {
+                write!(f, "The plural forms of the locale \"{}\" are not known", locale)
+            }
+            Error::InvalidPattern { pattern, error } => {
+                write!(f, "Invalid key pattern \"{}\": {}", pattern, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            Error::InvalidPattern { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
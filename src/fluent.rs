@@ -1,9 +1,19 @@
-use crate::parser::{Comment, Node};
+use crate::error::Error;
+use crate::parser::{
+    properties::printf_pattern, Comment, Diagnostic, Message, Node, Pattern, PatternElement,
+    Placeholder, Tag,
+};
+use crate::plural::{is_plural_note, plural_categories, split_variants};
+use crate::selection::KeySelection;
 use convert_case::{Case, Casing};
-use std::collections::HashMap;
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
 
-const LICENSE: &'static str =
-    "# This Source Code Form is subject to the terms of the Mozilla Public
+/// The license and description that start every .ftl file.
+pub const HEADER: &str = "# This Source Code Form is subject to the terms of the Mozilla Public
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
@@ -11,86 +21,1633 @@ const LICENSE: &'static str =
 
 ";
 
+/// The resource comment of the header, which is replaced when the file has a description.
+const DESCRIPTION_TODO: &str = "### TODO - Write a description of what this FTL represents.\n";
+
+/// The DTD entities that are Fluent terms in Firefox.
+const BUILT_IN_TERMS: &[(&str, &str)] = &[
+    ("brandShortName", "brand-short-name"),
+    ("brandShorterName", "brand-shorter-name"),
+    ("brandFullName", "brand-full-name"),
+    ("brandProductName", "brand-product-name"),
+    ("vendorShortName", "vendor-short-name"),
+];
+
+/// The suffixes of the keys that become attributes, like `save.label` and
+/// `save.accesskey`, which become the `.label` and `.accesskey` of `save`.
+pub const ATTRIBUTES: &[&str] = &[
+    "label",
+    "accesskey",
+    "title",
+    "tooltiptext",
+    "placeholder",
+    "value",
+    "aria-label",
+    "key",
+    "commandkey",
+];
+
+/// Serialize nodes to the text of a .ftl file with the default options.
 pub fn nodes_to_fluent(nodes: &Vec<Node>) -> String {
-    let mut key_to_node: HashMap<&str, usize> = HashMap::new();
+    Serializer::default().serialize(nodes).0
+}
 
-    for (index, node) in nodes.iter().enumerate() {
-        if let Node::Comment(Comment {
-            value: _,
-            key: Some(key),
-        }) = node
-        {
-            key_to_node.insert(key, index);
+/// Push the lines of a comment, prefixed with the `#`, `##` or `###` of its kind.
+fn push_comment(text: &mut String, prefix: &str, value: &str) {
+    for line in value.lines() {
+        text.push_str(prefix);
+        if !line.is_empty() {
+            text.push(' ');
+            text.push_str(line);
         }
+        text.push('\n');
+    }
+}
+
+/// The Fluent ID of a key, like `save-button-label` for `saveButton.label`.
+fn message_id(key: &str) -> String {
+    key.replace('.', "-").to_case(Case::Kebab)
+}
+
+/// The IDs of the messages in the text of a .ftl file, like the one that a partial
+/// migration is appended to. Terms and attributes aren't messages.
+pub fn fluent_ids(text: &str) -> Vec<&str> {
+    text.lines()
+        .filter_map(|line| {
+            let end = line
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(line.len());
+            let (id, rest) = line.split_at(end);
+            if id.starts_with(|c: char| c.is_ascii_alphabetic())
+                && rest.trim_start_matches(' ').starts_with('=')
+            {
+                Some(id)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Join the items of a list into a sentence, like `a, b and c`.
+fn format_list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
     }
+}
 
-    let mut iter = nodes.iter().peekable();
-    // Check for the license, and skip it if it exists.
-    if let Some(Node::Comment(comment)) = iter.peek() {
-        if comment.value.contains("http://mozilla.org/MPL/2.0/") {
-            iter.next();
+/// Push the `=` and the value of a message or an attribute, indented past it. The
+/// relative indentation of the lines of a block is kept, as Fluent only removes the
+/// indentation that all of the lines have in common.
+fn push_value(text: &mut String, indent: &str, value: &str) {
+    if value.contains('\n') {
+        text.push_str(" =\n");
+        for line in value.trim().lines() {
+            if !line.trim().is_empty() {
+                text.push_str(indent);
+                text.push_str("  ");
+                text.push_str(line.trim_end());
+            }
+            text.push('\n');
         }
+    } else {
+        text.push_str(" = ");
+        text.push_str(value);
+        text.push('\n');
     }
+}
 
-    let mut text: String = LICENSE.into();
-    for node in iter {
-        match node {
-            Node::Message(entity) => {
-                // Add a comment that belongs to this.
-                if let Some(comment_index) = key_to_node.get(entity.key) {
-                    if let Some(Node::Comment(comment)) = nodes.get(*comment_index) {
-                        text.push_str("\n");
-                        for line in comment.value.lines() {
-                            let mut line = line.trim();
-                            let mut char_iter = line.chars();
-                            if let Some('-') = char_iter.next() {
-                                line = char_iter.as_str().trim();
-                            }
+/// A message of the .ftl file. It's either a message of the source, or a group of
+/// messages of the source that share a key, like `save.label` and `save.accesskey`,
+/// which become the value and the attributes of one message.
+struct Group<'n, 'a> {
+    /// The key of the message, without the suffixes of the attributes.
+    key: &'n str,
+    /// The index of each message in the nodes, and the attribute it becomes, or `None`
+    /// for the value. The value comes first.
+    members: Vec<(usize, Option<&'n str>, &'n Message<'a>)>,
+}
 
-                            text.push_str("# ");
-                            text.push_str(line.trim());
-                            text.push_str("\n");
-                        }
+/// An argument of a message that becomes a variable.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Argument {
+    /// A printf-style placeholder, by position.
+    Placeholder(usize),
+    /// A number in a `PluralForm.jsm` string, like `#1`.
+    Number(usize),
+}
+
+/// Find the numbers of a `PluralForm.jsm` string, like `#1` and `#2`.
+fn plural_numbers() -> &'static Regex {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    NUMBER.get_or_init(|| Regex::new(r"#([0-9]+)").unwrap())
+}
+
+/// Find the names of the variables that a comment gives to arguments, like
+/// "%1$S is the host name", "%S will be replaced with the file name", "%2$S the
+/// port" or "#1 is the number of files".
+fn variable_hints(comment: &str) -> Vec<(Argument, String)> {
+    static HINT: OnceLock<Regex> = OnceLock::new();
+    let hint = HINT.get_or_init(|| {
+        Regex::new(
+            r"^\s*(?:(?:(?:is|are|will be|gets|becomes|will become|represents|stands for)(?:\s+replaced\s+(?:with|by))?|=|:|-)\s+(?:(?:the|a|an)\s+)?|(?:the|a|an)\s+)([A-Za-z][A-Za-z0-9_-]*(?:\s+[A-Za-z][A-Za-z0-9_-]*){0,2})",
+        )
+        .unwrap()
+    });
+    // The name ends before these words, like in "the number of tabs".
+    const STOP_WORDS: &[&str] = &[
+        "of", "for", "that", "which", "who", "in", "on", "to", "and", "or", "with", "from", "when",
+        "where", "is", "are", "as", "by",
+    ];
+    let name = |text: &str| {
+        let captures = hint.captures(text)?;
+        let words: Vec<&str> = captures[1]
+            .split_whitespace()
+            .take_while(|word| !STOP_WORDS.contains(&word.to_lowercase().as_str()))
+            .collect();
+        if words.is_empty() {
+            None
+        } else {
+            Some(words.join(" ").to_case(Case::Camel))
+        }
+    };
+
+    let pattern = printf_pattern(comment);
+    let mut hints = Vec::new();
+    for (index, element) in pattern.elements.iter().enumerate() {
+        match (element, pattern.elements.get(index + 1)) {
+            (PatternElement::Placeholder(placeholder), Some(PatternElement::Text(text))) => {
+                if let Some(name) = name(text) {
+                    hints.push((Argument::Placeholder(placeholder.position), name));
+                }
+            }
+            (PatternElement::Text(text), _) => {
+                for captures in plural_numbers().captures_iter(text) {
+                    let number = captures[1].parse().unwrap_or(0);
+                    let rest = &text[captures.get(0).map_or(0, |m| m.end())..];
+                    if let Some(name) = name(rest) {
+                        hints.push((Argument::Number(number), name));
                     }
                 }
+            }
+            _ => {}
+        }
+    }
+    hints
+}
+
+/// Holds the options for serializing nodes to Fluent.
+#[derive(Debug, Clone)]
+pub struct Serializer {
+    /// Maps the names of entities to the Fluent terms that replace references to them.
+    terms: HashMap<String, String>,
+    /// The names of the variables for the placeholders that no comment names, by
+    /// position.
+    variables: Vec<String>,
+    /// The messages that are lists of plural forms, even without a comment that says so.
+    plurals: KeySelection,
+    /// The CLDR plural categories of the plural forms in the source locale.
+    plural_categories: &'static [&'static str],
+    /// The suffixes of the keys that become attributes of a message.
+    attributes: Vec<String>,
+    /// The IDs that are already used, like those of the other .ftl files that are
+    /// loaded together with this one.
+    reserved_ids: HashSet<String>,
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Serializer {
+            terms: BUILT_IN_TERMS
+                .iter()
+                .map(|&(entity, term)| (entity.into(), term.into()))
+                .collect(),
+            variables: Vec::new(),
+            plurals: KeySelection::new(),
+            plural_categories: plural_categories("en-US").unwrap_or(&["one", "other"]),
+            attributes: ATTRIBUTES
+                .iter()
+                .map(|&attribute| attribute.into())
+                .collect(),
+            reserved_ids: HashSet::new(),
+        }
+    }
+}
+
+impl Serializer {
+    pub fn new() -> Self {
+        Serializer::default()
+    }
+
+    /// Convert references to an entity into references to a Fluent term, like
+    /// `&brandShortName;` into `{ -brand-short-name }`. The leading `-` of the term is
+    /// optional.
+    pub fn term(mut self, entity: impl Into<String>, term: &str) -> Self {
+        self.terms
+            .insert(entity.into(), term.trim_start_matches('-').into());
+        self
+    }
+
+    /// Add a name for the variables of placeholders that no comment names. The first
+    /// name is for the placeholders in the first position, like `%1$S` or the first
+    /// `%S`, the second name for the second position, and so on. Without a name they
+    /// become `$var`, or `$var1`, `$var2` and so on when a message has several of them.
+    pub fn fallback_variable(mut self, name: impl Into<String>) -> Self {
+        self.variables.push(name.into());
+        self
+    }
+
+    /// Convert the selected messages from lists of plural forms, like `#1 file;#1 files`,
+    /// into select expressions. Messages with a comment about plural forms are converted
+    /// without this.
+    pub fn plurals(mut self, selection: KeySelection) -> Self {
+        self.plurals = selection;
+        self
+    }
+
+    /// Set the locale of the source files, which decides the plural categories of the
+    /// plural forms. The default is `en-US`.
+    pub fn source_locale(mut self, locale: &str) -> Result<Self, Error> {
+        self.plural_categories =
+            plural_categories(locale).ok_or_else(|| Error::UnsupportedLocale(locale.into()))?;
+        Ok(self)
+    }
+
+    /// Set the suffixes of the keys that become attributes, replacing the default ones
+    /// of `ATTRIBUTES`. Messages whose keys only differ by these suffixes, like
+    /// `save.label` and `save.accesskey`, become one message with an attribute for each,
+    /// and a message with the key without the suffix, like `save`, becomes its value.
+    /// Without any suffixes, every message stays on its own.
+    pub fn attributes<S: Into<String>>(mut self, suffixes: impl IntoIterator<Item = S>) -> Self {
+        self.attributes = suffixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Treat the IDs as used already, like the IDs of the other .ftl files that are
+    /// loaded together with this one. A message that would get one of them gets another
+    /// ID instead, with a warning.
+    pub fn reserve_ids<S: Into<String>>(mut self, ids: impl IntoIterator<Item = S>) -> Self {
+        self.reserved_ids.extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Check if references to the entity become references to a Fluent term.
+    pub fn has_term(&self, entity: &str) -> bool {
+        self.terms.contains_key(entity)
+    }
+
+    /// Serialize the nodes to the text of a .ftl file. Anything that was converted but
+    /// needs a manual review is reported in the diagnostics.
+    pub fn serialize(&self, nodes: &[Node]) -> (String, Vec<Diagnostic>) {
+        let (mut text, messages, diagnostics) = self.serialize_parts(nodes);
+        text.push_str(&messages);
+        (text, diagnostics)
+    }
+
+    /// Serialize the nodes like `serialize`, but return the header of the file, with its
+    /// license and description, apart from the messages that follow it.
+    pub fn serialize_parts(&self, nodes: &[Node]) -> (String, String, Vec<Diagnostic>) {
+        let mut diagnostics = Vec::new();
+        let notes: Vec<&Comment> = nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Comment(comment) if comment.is_note() => Some(comment),
+                _ => None,
+            })
+            .collect();
 
-                // Add the key.
-                text.push_str(&entity.key.replace('.', "-").to_case(Case::Kebab));
+        let mut nodes = nodes;
+        // Check for the license, and skip it if it exists.
+        if let [Node::Comment(comment), rest @ ..] = nodes {
+            if comment.value.contains("http://mozilla.org/MPL/2.0/") {
+                nodes = rest;
+            }
+        }
+
+        let mut text: String = HEADER.into();
+        // A comment that starts the file, and isn't about a message, describes the file.
+        if let [Node::Comment(comment), rest @ ..] = nodes {
+            if !comment.is_note() && !comment.is_attached(None, rest.first()) {
+                let mut description = String::new();
+                push_comment(&mut description, "###", &comment.value);
+                text = text.replacen(DESCRIPTION_TODO, &description, 1);
+                nodes = rest;
+            }
+        }
+        let header_len = text.len();
 
-                // Push on the = part
-                if entity.value.contains("\n") {
-                    text.push_str(" =");
-                    text.push_str("\n");
-                    for line in entity.value.trim().lines() {
-                        text.push_str("  ");
+        // The comments directly above a message, with no blank line between them, by the
+        // index of their message.
+        let attached: HashMap<usize, &Comment> = nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, node)| match node {
+                Node::Comment(comment)
+                    if comment.is_attached(
+                        index.checked_sub(1).and_then(|i| nodes.get(i)),
+                        nodes.get(index + 1),
+                    ) =>
+                {
+                    Some((index + 1, comment))
+                }
+                _ => None,
+            })
+            .collect();
+        let mut groups = self.groups(nodes);
+        let ids = self.ids(&groups, &mut diagnostics);
+        for (index, node) in nodes.iter().enumerate() {
+            match node {
+                Node::Message(_) => {
+                    // The messages of a group are serialized together, where the first
+                    // of them is.
+                    let group = match groups.remove(&index) {
+                        Some(group) => group,
+                        None => continue,
+                    };
+                    // The notes can be about several messages, but each note is only
+                    // added once.
+                    let mut comments: Vec<&Comment> = Vec::new();
+                    let mut markup_notes = Vec::new();
+                    let mut values = Vec::new();
+                    for &(member, attribute, message) in &group.members {
+                        let member_comments: Vec<&Comment> = attached
+                            .get(&member)
+                            .copied()
+                            .into_iter()
+                            .chain(
+                                notes
+                                    .iter()
+                                    .copied()
+                                    .filter(|note| note.is_note_for(&message.key)),
+                            )
+                            .collect();
+                        let (value, notes) =
+                            self.value(message, &member_comments, &mut diagnostics);
+                        for comment in member_comments {
+                            if !comments.iter().any(|added| std::ptr::eq(*added, comment)) {
+                                comments.push(comment);
+                            }
+                        }
+                        markup_notes.extend(notes);
+                        values.push((attribute, value));
+                    }
+
+                    if (!comments.is_empty() || !markup_notes.is_empty()) && !text.ends_with("\n\n")
+                    {
+                        text.push('\n');
+                    }
+                    // Add the comments that belong to this.
+                    for comment in comments {
+                        push_comment(&mut text, "#", &comment.value);
+                    }
+
+                    // Describe the elements that need to be added to the DOM.
+                    for line in markup_notes {
+                        text.push_str("# ");
+                        text.push_str(&line);
+                        text.push('\n');
+                    }
+
+                    // Add the key, and then the value and the attributes.
+                    text.push_str(&ids[&index]);
+                    if values
+                        .first()
+                        .is_none_or(|(attribute, _)| attribute.is_some())
+                    {
+                        text.push('\n');
+                    }
+                    for (attribute, value) in values {
+                        match attribute {
+                            Some(attribute) => {
+                                text.push_str("  .");
+                                text.push_str(attribute);
+                                push_value(&mut text, "  ", &value);
+                            }
+                            None => push_value(&mut text, "", &value),
+                        }
+                    }
+                }
+                Node::Comment(comment) => {
+                    if comment.is_note() {
+                        continue;
+                    }
+                    if attached.contains_key(&(index + 1)) {
+                        continue;
+                    }
+                    // Standalone comments become group comments.
+                    {
+                        let mut chars = text.chars();
+                        chars.next_back();
+                        if chars.next_back() != Some('\n') {
+                            text.push('\n');
+                        }
+                    }
+                    push_comment(&mut text, "##", &comment.value);
+                    text.push('\n');
+                }
+                // The included entities were resolved before serializing.
+                Node::Include(_) => {}
+                Node::Junk(junk) => {
+                    // Keep the original source around as a standalone comment, so that it
+                    // can be migrated by hand.
+                    if !text.ends_with("\n\n") {
+                        text.push('\n');
+                    }
+                    text.push_str(
+                        "# TODO - This could not be migrated automatically and needs a manual review.\n",
+                    );
+                    text.push_str("# ");
+                    text.push_str(&junk.diagnostic.to_string());
+                    text.push_str("\n#\n");
+                    for line in junk.content.trim_end().lines() {
+                        text.push_str("#   ");
                         text.push_str(line);
-                        text.push_str("\n");
+                        text.push('\n');
                     }
-                } else {
-                    text.push_str(" = ");
-                    text.push_str(&entity.value);
                     text.push('\n');
                 }
             }
-            Node::Comment(comment) => {
-                if comment.key.is_some() {
+        }
+        let messages = text.split_off(header_len);
+        (text, messages, diagnostics)
+    }
+
+    /// The key and the Fluent ID of each message of the .ftl file, in order. The key of
+    /// a message with attributes is the key without their suffixes.
+    pub fn message_ids(&self, nodes: &[Node]) -> Vec<(String, String)> {
+        let groups = self.groups(nodes);
+        let mut ids = self.ids(&groups, &mut Vec::new());
+        let mut indices: Vec<usize> = groups.keys().copied().collect();
+        indices.sort_unstable();
+        indices
+            .into_iter()
+            .filter_map(|index| Some((groups[&index].key.to_string(), ids.remove(&index)?)))
+            .collect()
+    }
+
+    /// Choose the ID of each message, by the index of its group. Keys are converted to
+    /// IDs like `save-button` for `saveButton`, so different keys can end up with the
+    /// same ID, like `fooBar`, `foo-bar` and `foo.bar`. The first of them in the file
+    /// keeps the ID, and the rest get the first of `foo-bar-2`, `foo-bar-3` and so on
+    /// that is free, with a diagnostic. The same goes for a key that is defined more
+    /// than once, and for the reserved IDs, except that none of the keys can keep them.
+    fn ids(
+        &self,
+        groups: &HashMap<usize, Group>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> HashMap<usize, String> {
+        let mut indices: Vec<usize> = groups.keys().copied().collect();
+        indices.sort_unstable();
+        let mut ids: HashMap<usize, String> = indices
+            .iter()
+            .map(|&index| (index, message_id(groups[&index].key)))
+            .collect();
+
+        // The keys that become each ID, in the order of the file.
+        let mut keys_of_id: Vec<(String, Vec<(&str, usize)>)> = Vec::new();
+        for &index in &indices {
+            let key = groups[&index].key;
+            match keys_of_id.iter_mut().find(|(id, _)| *id == ids[&index]) {
+                Some((_, keys)) => keys.push((key, index)),
+                None => keys_of_id.push((ids[&index].clone(), vec![(key, index)])),
+            }
+        }
+
+        let mut taken: HashSet<String> = ids.values().cloned().collect();
+        taken.extend(self.reserved_ids.iter().cloned());
+        let mut renamed: HashMap<usize, String> = HashMap::new();
+        for (id, keys) in keys_of_id {
+            let is_reserved = self.reserved_ids.contains(&id);
+            if keys.len() < 2 && !is_reserved {
+                continue;
+            }
+            let mut new_ids = Vec::new();
+            for (number, &(_, index)) in keys.iter().enumerate() {
+                let new_id = if number == 0 && !is_reserved {
+                    id.clone()
+                } else {
+                    (2..)
+                        .map(|count| format!("{}-{}", id, count))
+                        .find(|candidate| taken.insert(candidate.clone()))
+                        .unwrap_or_default()
+                };
+                renamed.insert(index, new_id.clone());
+                new_ids.push(new_id);
+            }
+
+            // Report it at the first message that was renamed.
+            let renamed_index = keys[if is_reserved { 0 } else { 1 }].1;
+            let (_, _, renamed_message) = groups[&renamed_index]
+                .members
+                .iter()
+                .find(|(member, _, _)| *member == renamed_index)
+                .unwrap_or(&groups[&renamed_index].members[0]);
+            let is_repeated = keys.len() > 1;
+            // The keys that are defined more than once are only listed once.
+            let mut distinct_keys: Vec<String> = Vec::new();
+            for (key, _) in &keys {
+                let key = format!("\"{}\"", key);
+                if !distinct_keys.contains(&key) {
+                    distinct_keys.push(key);
+                }
+            }
+            let keys = distinct_keys;
+            let message = match (keys.len(), is_reserved) {
+                (1, false) => format!(
+                    "The key {} is defined more than once, so its messages were converted to {}",
+                    keys[0], format_list(&new_ids)
+                ),
+                (1, true) if is_repeated => format!(
+                    "The key {} is defined more than once, and another file already has its ID {}, so its messages were converted to {}",
+                    keys[0], id, format_list(&new_ids)
+                ),
+                (1, true) => format!(
+                    "The key {} becomes the ID {}, which another file already has, so it was converted to {}",
+                    keys[0], id, new_ids[0]
+                ),
+                (_, false) => format!(
+                    "The keys {} all become the ID {}, so they were converted to {}",
+                    format_list(&keys), id, format_list(&new_ids)
+                ),
+                (_, true) => format!(
+                    "The keys {} all become the ID {}, which another file already has, so they were converted to {}",
+                    format_list(&keys), id, format_list(&new_ids)
+                ),
+            };
+            diagnostics.push(Diagnostic {
+                message,
+                position: renamed_message.span.start,
+            });
+        }
+
+        ids.extend(renamed);
+        ids
+    }
+
+    /// Group the messages that become the attributes of one message, by the index of
+    /// the first message of each group. A key only groups messages when there are
+    /// several of them, with at most one value and no attribute twice.
+    fn groups<'n, 'a>(&self, nodes: &'n [Node<'a>]) -> HashMap<usize, Group<'n, 'a>> {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut group_of_key: HashMap<&str, usize> = HashMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            if let Node::Message(message) = node {
+                let (key, attribute) = match message.key.rsplit_once('.') {
+                    Some((key, suffix)) if self.attributes.iter().any(|a| a == suffix) => {
+                        (key, Some(suffix))
+                    }
+                    _ => (&*message.key, None),
+                };
+                let group = *group_of_key.entry(key).or_insert_with(|| {
+                    groups.push(Group {
+                        key,
+                        members: Vec::new(),
+                    });
+                    groups.len() - 1
+                });
+                groups[group].members.push((index, attribute, message));
+            }
+        }
+
+        let mut groups_by_index = HashMap::new();
+        for mut group in groups {
+            let mut names = HashSet::new();
+            let is_group = group.members.len() > 1
+                && group
+                    .members
+                    .iter()
+                    .all(|(_, attribute, _)| names.insert(*attribute));
+            if is_group {
+                let first = group.members[0].0;
+                group
+                    .members
+                    .sort_by_key(|(_, attribute, _)| attribute.is_some());
+                groups_by_index.insert(first, group);
+            } else {
+                for (index, _, message) in group.members {
+                    groups_by_index.insert(
+                        index,
+                        Group {
+                            key: &message.key,
+                            members: vec![(index, None, message)],
+                        },
+                    );
+                }
+            }
+        }
+        groups_by_index
+    }
+
+    /// Serialize the value of a message. Entity references become term references, and
+    /// a diagnostic is reported for the entities that aren't known terms. Placeholders
+    /// become variables, named after the comments of the message, and lists of plural
+    /// forms become select expressions. Markup is converted for the DOM overlays of
+    /// Fluent, and the lines of a comment that describe the changes it needs in the DOM
+    /// are returned with the value.
+    fn value(
+        &self,
+        message: &Message,
+        comments: &[&Comment],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> (String, Vec<String>) {
+        let mut markup = Markup::default();
+        if self.is_plural(message, comments) {
+            if let Some(text) = self.plural(message, comments, &mut markup, diagnostics) {
+                return (escape_value(text), markup.notes());
+            }
+        }
+        let variables =
+            self.variables(message, message.value.placeholders(), comments, diagnostics);
+        let mut text = String::new();
+        self.push_elements(
+            &mut text,
+            &message.value.elements,
+            message,
+            &variables,
+            &mut markup,
+            diagnostics,
+        );
+        (escape_value(text), markup.notes())
+    }
+
+    /// Serialize the elements of the value of a message.
+    fn push_elements(
+        &self,
+        text: &mut String,
+        elements: &[PatternElement],
+        message: &Message,
+        variables: &HashMap<usize, String>,
+        markup: &mut Markup,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let has_markup = message.value.has_markup();
+        for element in elements {
+            match element {
+                PatternElement::Text(value) if has_markup => {
+                    // The value will be parsed as HTML.
+                    let value = value.replace('&', "&amp;").replace('<', "&lt;");
+                    push_text(text, &value)
+                }
+                PatternElement::Text(value) => push_text(text, value),
+                PatternElement::EntityReference(name) => {
+                    let term = match self.terms.get(name) {
+                        Some(term) => term.clone(),
+                        None => {
+                            let term = name.replace('.', "-").to_case(Case::Kebab);
+                            diagnostics.push(Diagnostic {
+                                message: format!(
+                                    "The unknown entity &{}; in \"{}\" was converted to the term -{}, check that it exists",
+                                    name, message.key, term
+                                ),
+                                position: message.span.start,
+                            });
+                            term
+                        }
+                    };
+                    text.push_str("{ -");
+                    text.push_str(&term);
+                    text.push_str(" }");
+                }
+                PatternElement::StartTag(tag) => markup.start_tag(text, tag),
+                PatternElement::EndTag(name) => markup.end_tag(text, name),
+                PatternElement::Placeholder(placeholder) => {
+                    text.push_str("{ $");
+                    text.push_str(&variables[&placeholder.position]);
+                    text.push_str(" }");
+                }
+            }
+        }
+    }
+
+    /// Check if a message is a list of plural forms for `PluralForm.jsm`.
+    fn is_plural(&self, message: &Message, comments: &[&Comment]) -> bool {
+        if self.plurals.matches(&message.key) {
+            return true;
+        }
+        let has_forms = message.value.elements.iter().any(|element| match element {
+            PatternElement::Text(text) => text.contains(';'),
+            _ => false,
+        });
+        (has_forms || self.plural_categories.len() == 1)
+            && comments
+                .iter()
+                .any(|comment| is_plural_note(&comment.value))
+    }
+
+    /// Serialize a list of plural forms as a select expression on the number, with a
+    /// variant for each plural category of the source locale. The numbers like `#1`
+    /// become variables. Nothing is returned when the number of forms doesn't match
+    /// the plural categories.
+    fn plural(
+        &self,
+        message: &Message,
+        comments: &[&Comment],
+        markup: &mut Markup,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<String> {
+        let variants = split_variants(&message.value);
+        if variants.len() != self.plural_categories.len() {
+            diagnostics.push(Diagnostic {
+                message: format!(
+                    "The plural forms of \"{}\" weren't converted, as there are {} of them instead of {}",
+                    message.key,
+                    variants.len(),
+                    self.plural_categories.len()
+                ),
+                position: message.span.start,
+            });
+            return None;
+        }
+
+        let placeholders = variants.iter().flat_map(|variant| variant.placeholders());
+        let variables = &self.variables(message, placeholders, comments, diagnostics);
+
+        // Name the numbers after the comments, or after the placeholder of the count.
+        let hints: Vec<(Argument, String)> = comments
+            .iter()
+            .flat_map(|comment| variable_hints(&comment.value))
+            .collect();
+        let mut numbers: Vec<usize> = variants
+            .iter()
+            .flat_map(|variant| variant.elements.iter())
+            .filter_map(|element| match element {
+                PatternElement::Text(text) => Some(text),
+                _ => None,
+            })
+            .flat_map(|text| plural_numbers().captures_iter(text))
+            .filter_map(|captures| captures[1].parse().ok())
+            .collect();
+        // The forms don't always contain the number, like in `One tab;%S tabs`.
+        let has_count = numbers.contains(&1);
+        numbers.push(1);
+        numbers.sort_unstable();
+        numbers.dedup();
+        let mut taken: HashSet<String> = variables.values().cloned().collect();
+        let mut names = HashMap::new();
+        for number in numbers {
+            let hint = hints
+                .iter()
+                .find(|(argument, _)| *argument == Argument::Number(number))
+                .map(|(_, name)| name.clone());
+            let name = match (hint, number) {
+                (Some(name), _) => name,
+                // The count is often a placeholder instead, like in `%S file;%S files`.
+                (None, 1) if !has_count && variables.len() == 1 => {
+                    names.insert(
+                        number,
+                        variables.values().next().cloned().unwrap_or_default(),
+                    );
                     continue;
                 }
-                {
-                    let mut chars = text.chars();
-                    chars.next_back();
-                    if chars.next_back() != Some('\n') {
-                        text.push('\n');
+                (None, 1) => "count".into(),
+                (None, number) => format!("number{}", number),
+            };
+            let name = if taken.contains(&name) {
+                format!("{}{}", name, number)
+            } else {
+                name
+            };
+            taken.insert(name.clone());
+            names.insert(number, name);
+        }
+
+        let mut text = String::new();
+        if let [variant] = &variants[..] {
+            // There is nothing to select with a single form.
+            text.push_str(&self.variant(variant, &names, message, variables, markup, diagnostics));
+            return Some(text);
+        }
+        text.push_str(&format!("{{ ${} ->\n", names[&1]));
+        for (index, (category, variant)) in self.plural_categories.iter().zip(&variants).enumerate()
+        {
+            // The last variant is the default, unless there is an `other` category.
+            let is_default = if self.plural_categories.contains(&"other") {
+                *category == "other"
+            } else {
+                index == variants.len() - 1
+            };
+            text.push_str(if is_default { " *[" } else { "  [" });
+            text.push_str(category);
+            text.push_str("] ");
+            markup.start_variant();
+            text.push_str(&self.variant(variant, &names, message, variables, markup, diagnostics));
+            text.push('\n');
+        }
+        text.push('}');
+        Some(text)
+    }
+
+    /// Serialize one of the plural forms of a message, with its numbers named.
+    fn variant(
+        &self,
+        variant: &Pattern,
+        names: &HashMap<usize, String>,
+        message: &Message,
+        variables: &HashMap<usize, String>,
+        markup: &mut Markup,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> String {
+        let mut variant_text = String::new();
+        for element in &variant.elements {
+            match element {
+                PatternElement::Text(value) => {
+                    let mut start = 0;
+                    for captures in plural_numbers().captures_iter(value) {
+                        let number = captures.get(0).unwrap();
+                        let name = captures[1].parse().ok().and_then(|n: usize| names.get(&n));
+                        if let Some(name) = name {
+                            push_text(&mut variant_text, &value[start..number.start()]);
+                            variant_text.push_str("{ $");
+                            variant_text.push_str(name);
+                            variant_text.push_str(" }");
+                            start = number.end();
+                        }
                     }
+                    push_text(&mut variant_text, &value[start..]);
                 }
-                for line in comment.value.lines() {
-                    text.push_str("## ");
-                    text.push_str(line);
-                    text.push('\n');
+                element => self.push_elements(
+                    &mut variant_text,
+                    std::slice::from_ref(element),
+                    message,
+                    variables,
+                    markup,
+                    diagnostics,
+                ),
+            }
+        }
+        escape_value(variant_text)
+    }
+
+    /// Name the variables of the placeholders in a message, by their position. The
+    /// names come from the comments of the message, like "%1$S is the host name", or
+    /// else from the fallback variables. A diagnostic is reported for the names that
+    /// had to be made up.
+    fn variables<'p>(
+        &self,
+        message: &Message,
+        placeholders: impl Iterator<Item = &'p Placeholder>,
+        comments: &[&Comment],
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> HashMap<usize, String> {
+        let mut positions: Vec<(usize, &str)> = placeholders
+            .map(|placeholder| (placeholder.position, placeholder.source.as_str()))
+            .collect();
+        positions.sort_unstable();
+        positions.dedup_by_key(|(position, _)| *position);
+
+        let hints: Vec<(Argument, String)> = comments
+            .iter()
+            .flat_map(|comment| variable_hints(&comment.value))
+            .collect();
+        let mut variables = HashMap::new();
+        let mut taken = HashSet::new();
+        for &(position, source) in &positions {
+            let hint = hints
+                .iter()
+                .find(|(argument, _)| *argument == Argument::Placeholder(position))
+                .map(|(_, name)| name.clone());
+            let fallback = || match position.checked_sub(1).and_then(|i| self.variables.get(i)) {
+                Some(name) => name.clone(),
+                None if positions.len() == 1 && position == 1 => "var".into(),
+                None => format!("var{}", position),
+            };
+            let mut name = match hint {
+                Some(name) => name,
+                None => {
+                    let name = fallback();
+                    if self.variables.len() < position {
+                        diagnostics.push(Diagnostic {
+                            message: format!(
+                                "The placeholder {} in \"{}\" was converted to the variable ${}, check its name",
+                                source, message.key, name
+                            ),
+                            position: message.span.start,
+                        });
+                    }
+                    name
                 }
-                text.push('\n');
+            };
+            // Two placeholders can't share a variable.
+            if taken.contains(&name) {
+                name = format!("{}{}", name, position);
             }
+            taken.insert(name.clone());
+            variables.insert(position, name);
         }
+        variables
+    }
+}
+
+/// The elements that translations can contain as they are, without a matching element
+/// in the DOM. These are the text-level elements that the DOM overlays of Fluent allow.
+const TEXT_LEVEL_ELEMENTS: &[&str] = &[
+    "em", "strong", "small", "s", "cite", "q", "dfn", "abbr", "data", "time", "code", "var",
+    "samp", "kbd", "sub", "sup", "i", "b", "u", "mark", "bdi", "bdo", "span", "br", "wbr",
+];
+
+/// The attributes that translations can set on the elements of markup. Any other
+/// attribute has to be set in the DOM.
+const LOCALIZABLE_ATTRIBUTES: &[&str] = &[
+    "title",
+    "aria-label",
+    "aria-valuetext",
+    "alt",
+    "placeholder",
+];
+
+/// Converts the markup in a value for the DOM overlays of Fluent. Text-level elements
+/// are kept, and the other elements are matched to an element in the DOM through a
+/// `data-l10n-name`, so their attributes move to the DOM.
+#[derive(Default)]
+struct Markup {
+    /// The data-l10n-name of each element that needs to be in the DOM, along with its
+    /// original start tag.
+    named_elements: Vec<(String, String)>,
+    /// The index in `named_elements` where the current plural form starts. Each form has
+    /// the same elements, so their names are only unique within a form.
+    variant_start: usize,
+    /// The name of each text-level element with attributes that translations can't set,
+    /// along with those attributes. There is no element in the DOM to move them to.
+    removed_attributes: Vec<(String, String)>,
+}
+
+impl Markup {
+    /// Start the markup of the next plural form of a message.
+    fn start_variant(&mut self) {
+        self.variant_start = self.named_elements.len();
+    }
+
+    fn start_tag(&mut self, text: &mut String, tag: &Tag) {
+        let name = tag.local_name().to_lowercase();
+        text.push('<');
+        text.push_str(&name);
+        let is_text_level = TEXT_LEVEL_ELEMENTS.contains(&name.as_str());
+        if !is_text_level {
+            let l10n_name = self.l10n_name(tag);
+            text.push_str(&format!(" data-l10n-name=\"{}\"", l10n_name));
+            let pattern = Pattern {
+                elements: vec![PatternElement::StartTag(tag.clone())],
+            };
+            self.named_elements.push((l10n_name, pattern.to_string()));
+        }
+        let mut removed = Vec::new();
+        for (attribute, value) in &tag.attributes {
+            if is_text_level && !LOCALIZABLE_ATTRIBUTES.contains(&attribute.as_str()) {
+                removed.push(format!("{}=\"{}\"", attribute, value));
+            }
+            if LOCALIZABLE_ATTRIBUTES.contains(&attribute.as_str()) {
+                let value = value
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('"', "&quot;");
+                text.push_str(&format!(" {}=\"", attribute));
+                push_text(text, &value);
+                text.push('"');
+            }
+        }
+        text.push_str(if tag.self_closing { "/>" } else { ">" });
+        if !removed.is_empty() {
+            let removed = (name, removed.join(" "));
+            if !self.removed_attributes.contains(&removed) {
+                self.removed_attributes.push(removed);
+            }
+        }
+    }
+
+    fn end_tag(&mut self, text: &mut String, name: &str) {
+        let local_name = name.rsplit(':').next().unwrap_or(name);
+        text.push_str("</");
+        text.push_str(&local_name.to_lowercase());
+        text.push('>');
+    }
+
+    /// Choose a data-l10n-name for an element. It's the id of the element if it has
+    /// one, and otherwise the name of the element, with a number to keep it unique.
+    fn l10n_name(&self, tag: &Tag) -> String {
+        let base = match tag.attributes.iter().find(|(name, _)| name == "id") {
+            Some((_, id)) => id.to_case(Case::Kebab),
+            None if tag.local_name().eq_ignore_ascii_case("a") => "link".into(),
+            None => tag.local_name().to_lowercase(),
+        };
+        let is_taken = |name: &str| {
+            self.named_elements[self.variant_start..]
+                .iter()
+                .any(|(taken, _)| taken == name)
+        };
+        let mut name = base.clone();
+        let mut count = 1;
+        while is_taken(&name) {
+            count += 1;
+            name = format!("{}-{}", base, count);
+        }
+        name
+    }
+
+    /// The lines of a comment that list the elements the DOM needs, and the attributes
+    /// that were removed from the markup.
+    fn notes(self) -> Vec<String> {
+        let mut notes = Vec::new();
+        if !self.named_elements.is_empty() {
+            notes.push(
+                "TODO - The markup needs these elements in the DOM, with a data-l10n-name:".into(),
+            );
+            let mut listed = HashSet::new();
+            for (name, source) in &self.named_elements {
+                // The plural forms repeat the same elements.
+                if listed.insert((name, source)) {
+                    notes.push(format!("  {}: {}", name, source));
+                }
+            }
+        }
+        if !self.removed_attributes.is_empty() {
+            notes.push(
+                "TODO - Translations can't set these attributes of the markup, so they were removed:"
+                    .into(),
+            );
+            for (name, attributes) in self.removed_attributes {
+                notes.push(format!("  {}: {}", name, attributes));
+            }
+        }
+        notes
+    }
+}
+
+/// Characters that can't be seen, or are easily mistaken for others, like the no-break
+/// space and the zero-width characters. These are escaped in the .ftl so that they
+/// stay visible in a review.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{A0}'
+            | '\u{AD}'
+            | '\u{2000}'..='\u{200F}'
+            | '\u{2028}'..='\u{202F}'
+            | '\u{205F}'..='\u{206F}'
+            | '\u{3000}'
+            | '\u{FEFF}'
+    ) || (c.is_control() && c != '\n')
+}
+
+/// Push the text of a value, with anything that would be read as Fluent syntax written
+/// as a string literal, like `{ "{" }`. These are the braces of placeables, and the
+/// `[`, `*` and `.` of variants and attributes at the start of a line. Invisible
+/// characters are written as Unicode escapes in a string literal, like `{ "\u00A0" }`.
+fn push_text(text: &mut String, value: &str) {
+    // The text since the last line break is only indentation.
+    let mut at_line_start = match text.rfind('\n') {
+        Some(line_end) => text[line_end + 1..].trim_start_matches(' ').is_empty(),
+        None => false,
+    };
+    let mut in_literal = false;
+    for c in value.chars() {
+        let is_syntax = matches!(c, '{' | '}') || (at_line_start && matches!(c, '[' | '*' | '.'));
+        if is_invisible(c) || is_syntax {
+            if !in_literal {
+                text.push_str("{ \"");
+                in_literal = true;
+            }
+            match c as u32 {
+                _ if is_syntax => text.push(c),
+                code @ 0..=0xFFFF => text.push_str(&format!("\\u{:04X}", code)),
+                code => text.push_str(&format!("\\U{:06X}", code)),
+            }
+        } else {
+            if in_literal {
+                text.push_str("\" }");
+                in_literal = false;
+            }
+            text.push(c);
+        }
+        at_line_start = c == '\n' || (at_line_start && c == ' ');
+    }
+    if in_literal {
+        text.push_str("\" }");
+    }
+}
+
+/// Escape what Fluent would change about a serialized value. Fluent removes the spaces
+/// that start and end a value, so they are written as string literals, like `{ " " }`,
+/// and an empty value is written as `{ "" }`, as a message needs a value. A value of
+/// several lines becomes a block, where the `[`, `*` and `.` of its first line are
+/// syntax too.
+fn escape_value(value: String) -> String {
+    let mut content = value.trim_matches(' ');
+    if content.is_empty() {
+        return string_literal(&value);
+    }
+    let leading = &value[..value.len() - value.trim_start_matches(' ').len()];
+    let trailing = &value[value.trim_end_matches(' ').len()..];
+    let mut escaped = String::new();
+    if !leading.is_empty() {
+        escaped.push_str(&string_literal(leading));
+    } else if content.contains('\n') && content.starts_with(['[', '*', '.']) {
+        escaped.push_str(&string_literal(&content[..1]));
+        content = &content[1..];
+    }
+    escaped.push_str(content);
+    if !trailing.is_empty() {
+        escaped.push_str(&string_literal(trailing));
+    }
+    escaped
+}
+
+/// A Fluent string literal placeable of text that has nothing to escape.
+fn string_literal(text: &str) -> String {
+    format!("{{ \"{}\" }}", text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Serializes a .properties source, and returns the text after the header along with the
+    /// diagnostics.
+    fn serialize(serializer: Serializer, source: &str) -> (String, Vec<String>) {
+        serialize_nodes(serializer, &crate::parse_properties(source).unwrap())
+    }
+
+    fn serialize_dtd(serializer: Serializer, source: &str) -> (String, Vec<String>) {
+        serialize_nodes(serializer, &crate::parse_dtd(source).unwrap())
+    }
+
+    fn serialize_nodes(serializer: Serializer, nodes: &[Node]) -> (String, Vec<String>) {
+        let (text, diagnostics) = serializer.serialize(nodes);
+        let diagnostics = diagnostics.iter().map(|d| d.to_string()).collect();
+        (text.trim_start_matches(HEADER).to_string(), diagnostics)
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_push_text() {
+        let assert = |value, expected| {
+            let mut text = String::new();
+            push_text(&mut text, value);
+            assert_eq!(text, expected);
+        };
+
+        assert("Plain text", "Plain text");
+        assert("10\u{A0}MB", "10{ \"\\u00A0\" }MB");
+        assert("Zero\u{200B}\u{200D}width", "Zero{ \"\\u200B\\u200D\" }width");
+        assert("\u{FEFF}", "{ \"\\uFEFF\" }");
+        assert("Tab\tstop", "Tab{ \"\\u0009\" }stop");
+        assert("Wait… 😀", "Wait… 😀");
+        assert("{braces}", "{ \"{\" }braces{ \"}\" }");
+        assert("A {{0}}", "A { \"{{\" }0{ \"}}\" }");
+        assert("[not a variant]", "[not a variant]");
+        assert("Menu\n[1] Open\n*default\n  .attribute\na.b", "Menu\n{ \"[\" }1] Open\n{ \"*\" }default\n  { \".\" }attribute\na.b");
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_escape_value() {
+        let assert = |value: &str, expected| assert_eq!(escape_value(value.into()), expected);
+
+        assert("Plain text", "Plain text");
+        assert("", "{ \"\" }");
+        assert("   ", "{ \"   \" }");
+        assert("  Indented", "{ \"  \" }Indented");
+        assert("Trailing ", "Trailing{ \" \" }");
+        assert(" Both  ", "{ \" \" }Both{ \"  \" }");
+        assert("[inline]", "[inline]");
+        assert("*first\nsecond", "{ \"*\" }first\nsecond");
+        assert(".first\nsecond ", "{ \".\" }first\nsecond{ \" \" }");
+        assert("{ $var }", "{ $var }");
+    }
+
+    #[test]
+    fn test_escape_messages() {
+        assert_eq!(
+            serialize(
+                Serializer::default().plurals(KeySelection::new().key("plural.label")),
+                "braces = Use {0} here\nempty =\nspaces = Spaced \\u0020\nplural.label = #1 {file};#1 files\nplural.accesskey = \n",
+            )
+            .0,
+            "braces = Use { \"{\" }0{ \"}\" } here\nempty = { \"\" }\nspaces = Spaced{ \"  \" }\nplural\n  .label =\n    { $count ->\n      [one] { $count } { \"{\" }file{ \"}\" }\n     *[other] { $count } files\n    }\n  .accesskey = { \"\" }\n"
+        );
+
+        assert_eq!(
+            serialize_dtd(
+                Serializer::default(),
+                "<!ENTITY list \"[1] One\n*  Two\n.three\">\n"
+            )
+            .0,
+            "list =\n  { \"[\" }1] One\n  { \"*\" }  Two\n  { \".\" }three\n"
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# LOCALIZATION NOTE (back.label, back.accesskey): The back button.\nback.label = Back\nback.accesskey = B\n# LOCALIZATION NOTE (menu.*): A menu item.\n\n# LOCALIZATION NOTE (menu.open): Opens a file.\nmenu.open = Open\nmenu.close = Close\nother = Other\n",
+            )
+            .0,
+            "# The back button.\nback\n  .label = Back\n  .accesskey = B\n\n# A menu item.\n# Opens a file.\nmenu-open = Open\n\n# A menu item.\nmenu-close = Close\nother = Other\n"
+        );
+
+        // Each of the notes stacked in a block is about its own keys.
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# LOCALIZATION NOTE (a.title): First note.\n# LOCALIZATION NOTE (b.title): Second note.\na.title = A\nb.title = B\n",
+            )
+            .0,
+            "# First note.\na-title = A\n\n# Second note.\nb-title = B\n"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# This Source Code Form is subject to the terms of the Mozilla Public\n# License, v. 2.0. If a copy of the MPL was not distributed with this\n# file, You can obtain one at http://mozilla.org/MPL/2.0/.\n\n# Strings for the downloads panel.\n\n# The title of the panel.\ndownloads.title = Downloads\ndownloads.clear = Clear\n\n# Context menu\n\ndownloads.open = Open\n# Removes the download.\n\ndownloads.remove = Remove\n"
+            )
+            .0,
+            "# This Source Code Form is subject to the terms of the Mozilla Public\n# License, v. 2.0. If a copy of the MPL was not distributed with this\n# file, You can obtain one at http://mozilla.org/MPL/2.0/.\n\n### Strings for the downloads panel.\n\n# The title of the panel.\ndownloads-title = Downloads\ndownloads-clear = Clear\n\n## Context menu\n\ndownloads-open = Open\n\n## Removes the download.\n\ndownloads-remove = Remove\n"
+        );
+
+        // The description is part of the header, apart from the messages.
+        let nodes = crate::parse_properties(
+            "# Strings for the downloads panel.\n\n# The title of the panel.\ndownloads.title = Downloads\n",
+        )
+        .unwrap();
+        let (header, messages, _) = Serializer::default().serialize_parts(&nodes);
+        assert_eq!(
+            header,
+            "# This Source Code Form is subject to the terms of the Mozilla Public\n# License, v. 2.0. If a copy of the MPL was not distributed with this\n# file, You can obtain one at http://mozilla.org/MPL/2.0/.\n\n### Strings for the downloads panel.\n\n"
+        );
+        assert_eq!(
+            messages,
+            "# The title of the panel.\ndownloads-title = Downloads\n"
+        );
+
+        // A comment directly above the first message belongs to it.
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# The title of the panel.\ndownloads.title = Downloads\n"
+            )
+            .0,
+            "# The title of the panel.\ndownloads-title = Downloads\n"
+        );
+
+        // A comment after a message on the same line doesn't belong to the next message.
+        assert_eq!(
+            serialize_dtd(
+                Serializer::default(),
+                "<!ENTITY a.label \"A\"> <!-- About a. -->\n<!ENTITY b.label \"B\">\n",
+            )
+            .0,
+            "a-label = A\n\n## About a.\n\nb-label = B\n"
+        );
+
+        // The paragraphs and lists of a comment are kept.
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# Context menu\n#\n# The items are:\n#   - Open\n#   - Remove\n\n# LOCALIZATION NOTE (downloads.open): Opens the file,\n# or the folder.\n#\n# Don't translate \"file\".\ndownloads.open = Open\n"
+            )
+            .0,
+            "# This Source Code Form is subject to the terms of the Mozilla Public\n# License, v. 2.0. If a copy of the MPL was not distributed with this\n# file, You can obtain one at http://mozilla.org/MPL/2.0/.\n\n### Context menu\n###\n### The items are:\n###   - Open\n###   - Remove\n\n# Opens the file,\n# or the folder.\n#\n# Don't translate \"file\".\ndownloads-open = Open\n"
+        );
+
+        assert_eq!(
+            serialize_dtd(
+                Serializer::default(),
+                "<!-- The title of the panel. -->\n<!ENTITY downloads.title \"Downloads\">\n\n<!-- Context menu -->\n<!ENTITY downloads.open \"Open\">\n",
+            )
+            .0,
+            "# The title of the panel.\ndownloads-title = Downloads\n\n# Context menu\ndownloads-open = Open\n"
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let serialize = |serializer, source| serialize(serializer, source).0;
+
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# LOCALIZATION NOTE (saveButton.*): The save button.\n\n# Saves the page.\nsaveButton.label = Save\nsaveButton.accesskey = S\nopen = Open\n# Shown on hover.\nsaveButton.tooltiptext = Save %S\nsearch = Search\nsearch.placeholder = Search the web\nalone.title = Alone\n"
+            ),
+            "# Saves the page.\n# The save button.\n# Shown on hover.\nsave-button\n  .label = Save\n  .accesskey = S\n  .tooltiptext = Save { $var }\nopen = Open\nsearch = Search\n  .placeholder = Search the web\nalone-title = Alone\n"
+        );
+
+        // A value of several lines is a block, indented past its attribute.
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# plural forms\nfiles.label = #1 file;#1 files\nfiles.accesskey = F\n"
+            ),
+            "# plural forms\nfiles\n  .label =\n    { $count ->\n      [one] { $count } file\n     *[other] { $count } files\n    }\n  .accesskey = F\n"
+        );
+
+        // The same attribute twice can't be grouped, nor can anything without suffixes.
+        let source = "back.label = Back\nback.label = Back again\nback.accesskey = B\n";
+        assert_eq!(
+            serialize(Serializer::default(), source),
+            "back-label = Back\nback-label-2 = Back again\nback-accesskey = B\n"
+        );
+        assert_eq!(
+            serialize(
+                Serializer::default().attributes(Vec::<String>::new()),
+                "save.label = Save\nsave.accesskey = S\n"
+            ),
+            "save-label = Save\nsave-accesskey = S\n"
+        );
+        assert_eq!(
+            serialize(
+                Serializer::default().attributes(["label"]),
+                "save.label = Save\nsave.accesskey = S\nsave = Saved\n"
+            ),
+            "save = Saved\n  .label = Save\nsave-accesskey = S\n"
+        );
+    }
+
+    #[test]
+    fn test_fluent_ids() {
+        assert_eq!(
+            fluent_ids(&format!(
+                "{}\n# open = In a comment\nopen = Open\n  .accesskey = O\n-brand = Firefox\nsave-as=Save As\r\nmulti =\n  line = not an ID\n",
+                HEADER
+            )),
+            ["open", "save-as", "multi"]
+        );
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "fooBar = A\nfoo-bar-2 = B\nfoo.bar = C\nfoo-bar = D\nfooBar = E\nsave.label = Save\nsave.accesskey = S\nsave-label = Other\n"
+            ),
+            (
+                "foo-bar = A\nfoo-bar-2 = B\nfoo-bar-3 = C\nfoo-bar-4 = D\nfoo-bar-5 = E\nsave\n  .label = Save\n  .accesskey = S\nsave-label = Other\n".into(),
+                vec!["3:1: The keys \"fooBar\", \"foo.bar\" and \"foo-bar\" all become the ID foo-bar, so they were converted to foo-bar, foo-bar-3, foo-bar-4 and foo-bar-5".into()]
+            )
+        );
+
+        // A key that is defined more than once would define its message twice.
+        assert_eq!(
+            serialize(Serializer::default(), "back.label = Back\nback.label = Back again\n"),
+            (
+                "back-label = Back\nback-label-2 = Back again\n".into(),
+                vec!["2:1: The key \"back.label\" is defined more than once, so its messages were converted to back-label and back-label-2".into()]
+            )
+        );
+        assert_eq!(
+            serialize(
+                Serializer::default().reserve_ids(["back-label"]),
+                "back.label = Back\nback.label = Back again\n"
+            ),
+            (
+                "back-label-2 = Back\nback-label-3 = Back again\n".into(),
+                vec!["1:1: The key \"back.label\" is defined more than once, and another file already has its ID back-label, so its messages were converted to back-label-2 and back-label-3".into()]
+            )
+        );
+
+        // The IDs of other files can't be used.
+        let serializer = Serializer::default().reserve_ids(["downloads-title", "open"]);
+        assert_eq!(
+            serialize(serializer.clone(), "downloads.title = Downloads\nopen = Open\nopen-2 = Open too\n"),
+            (
+                "downloads-title-2 = Downloads\nopen-3 = Open\nopen-2 = Open too\n".into(),
+                vec![
+                    "1:1: The key \"downloads.title\" becomes the ID downloads-title, which another file already has, so it was converted to downloads-title-2".into(),
+                    "2:1: The key \"open\" becomes the ID open, which another file already has, so it was converted to open-3".into(),
+                ]
+            )
+        );
+        let nodes = crate::parse_properties(
+            "downloads.title = Downloads\nsave.label = Save\nsave.accesskey = S\n",
+        )
+        .unwrap();
+        assert_eq!(
+            serializer.message_ids(&nodes),
+            [
+                (
+                    "downloads.title".to_string(),
+                    "downloads-title-2".to_string()
+                ),
+                ("save".to_string(), "save".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_variable_hints() {
+        use Argument::{Number, Placeholder};
+        let assert = |comment, expected: &[(Argument, &str)]| {
+            let hints = variable_hints(comment);
+            let hints: Vec<(Argument, &str)> =
+                hints.iter().map(|(argument, name)| (*argument, name.as_str())).collect();
+            assert_eq!(hints, expected);
+        };
+
+        assert("%1$S is the host name, and %2$S is the port.", &[(Placeholder(1), "hostName"), (Placeholder(2), "port")]);
+        assert("%S will be replaced with the name of the file", &[(Placeholder(1), "name")]);
+        assert("%S = number of tabs, %S = window title", &[(Placeholder(1), "number"), (Placeholder(2), "windowTitle")]);
+        assert("%1$S: download speed", &[(Placeholder(1), "downloadSpeed")]);
+        assert("%1$S is the host, %2$S the port", &[(Placeholder(1), "host"), (Placeholder(2), "port")]);
+        assert("#1 is the file count; #2 is the folder.", &[(Number(1), "fileCount"), (Number(2), "folder")]);
+        assert("Shown in the %S menu.", &[]);
+        assert("No placeholders", &[]);
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# LOCALIZATION NOTE (connecting): %1$S is the host name, %2$S is the port.\nconnecting = Connecting to %1$S:%2$S (%2$S), 100%% done\n"
+            ),
+            (
+                "# %1$S is the host name, %2$S is the port.\nconnecting = Connecting to { $hostName }:{ $port } ({ $port }), 100% done\n".into(),
+                vec![]
+            )
+        );
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# %S is the file name\nopen = Open %S?\n"
+            ),
+            (
+                "# %S is the file name\nopen = Open { $fileName }?\n".into(),
+                vec![]
+            )
+        );
+        assert_eq!(
+            serialize(Serializer::default(), "size = %1$S of %2$S, %3$d%%\nsingle = Open %S\n"),
+            (
+                "size = { $var1 } of { $var2 }, { $var3 }%\nsingle = Open { $var }\n".into(),
+                vec![
+                    "1:1: The placeholder %1$S in \"size\" was converted to the variable $var1, check its name".into(),
+                    "1:1: The placeholder %2$S in \"size\" was converted to the variable $var2, check its name".into(),
+                    "1:1: The placeholder %3$d in \"size\" was converted to the variable $var3, check its name".into(),
+                    "2:1: The placeholder %S in \"single\" was converted to the variable $var, check its name".into(),
+                ]
+            )
+        );
+        // Positions start at 1, so this is only text.
+        assert_eq!(
+            serialize(Serializer::default(), "items = %0$S items\n"),
+            ("items = %0$S items\n".into(), vec![])
+        );
+
+        // The fallbacks are used by position.
+        let (text, diagnostics) = serialize(
+            Serializer::default()
+                .fallback_variable("name")
+                .fallback_variable("size"),
+            "# %2$S is the count\nsize = %1$S (%2$S %3$S)\n",
+        );
+        assert_eq!(
+            text,
+            "# %2$S is the count\nsize = { $name } ({ $count } { $var3 })\n"
+        );
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_plurals() {
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# LOCALIZATION NOTE (files): Semicolon list of plural forms.\n# #1 is the number of files, #2 the folder.\nfiles = #1 file in #2;#1 files in #2\n"
+            ),
+            (
+                "# Semicolon list of plural forms.\n# #1 is the number of files, #2 the folder.\nfiles =\n  { $number ->\n    [one] { $number } file in { $folder }\n   *[other] { $number } files in { $folder }\n  }\n".into(),
+                vec![]
+            )
+        );
+
+        // The count is a placeholder, and the plural is configured.
+        assert_eq!(
+            serialize(
+                Serializer::default().plurals(KeySelection::new().key("tabs")),
+                "tabs = One tab;%S tabs\n"
+            ),
+            (
+                "tabs =\n  { $var ->\n    [one] One tab\n   *[other] { $var } tabs\n  }\n".into(),
+                vec!["1:1: The placeholder %S in \"tabs\" was converted to the variable $var, check its name".into()]
+            )
+        );
+
+        // The plural forms of the source locale.
+        assert_eq!(
+            serialize(
+                Serializer::default().source_locale("ru").unwrap(),
+                "# plural forms\nfiles = #1 файл;#1 файла;#1 файлов\n"
+            ),
+            (
+                "# plural forms\nfiles =\n  { $count ->\n    [one] { $count } файл\n    [few] { $count } файла\n   *[many] { $count } файлов\n  }\n".into(),
+                vec![]
+            )
+        );
+        assert_eq!(
+            serialize(
+                Serializer::default().source_locale("ja").unwrap(),
+                "# plural forms\nfiles = #1 個のファイル\n"
+            ),
+            (
+                "# plural forms\nfiles = { $count } 個のファイル\n".into(),
+                vec![]
+            )
+        );
+        assert_eq!(
+            serialize(
+                Serializer::default().source_locale("ar").unwrap(),
+                "# plural forms\nfiles = #1 one;#1 two;#1 few;#1 many;#1 other;#1 zero\n"
+            ),
+            (
+                "# plural forms\nfiles =\n  { $count ->\n    [one] { $count } one\n    [two] { $count } two\n    [few] { $count } few\n    [many] { $count } many\n   *[other] { $count } other\n    [zero] { $count } zero\n  }\n".into(),
+                vec![]
+            )
+        );
+        assert!(Serializer::default().source_locale("xx").is_err());
+
+        // The forms don't match the plural categories.
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# plural forms\nfiles = #1 file;#1 files;#1 more files\n"
+            ),
+            (
+                "# plural forms\nfiles = #1 file;#1 files;#1 more files\n".into(),
+                vec!["2:1: The plural forms of \"files\" weren't converted, as there are 3 of them instead of 2".into()]
+            )
+        );
+
+        // The number and a placeholder are different variables.
+        assert_eq!(
+            serialize(
+                Serializer::default(),
+                "# plural forms, %S is the folder\nfiles = #1 file in %S;#1 files in %S\n"
+            ),
+            (
+                "# plural forms, %S is the folder\nfiles =\n  { $count ->\n    [one] { $count } file in { $folder }\n   *[other] { $count } files in { $folder }\n  }\n".into(),
+                vec![]
+            )
+        );
+
+        // Without a note, a list is left as it is.
+        assert_eq!(
+            serialize(Serializer::default(), "list = a;b\n"),
+            ("list = a;b\n".into(), vec![])
+        );
+    }
+
+    #[test]
+    fn test_markup() {
+        let serialize = |source| serialize_dtd(Serializer::default(), source);
+
+        assert_eq!(
+            serialize(
+                "<!ENTITY learn.label \"Read <html:strong class='x' title=\\\"A &amp; B\\\">more</html:strong> from &brandShortName; in <html:a href='https://example.com' id='learnMore'>the docs</html:a> &amp; <html:a href='https://example.org'>the wiki</html:a>&#160;<html:img src='icon.png'/> &lt;3\">",
+            ),
+            (
+                "# TODO - The markup needs these elements in the DOM, with a data-l10n-name:
+#   learn-more: <html:a href=\"https://example.com\" id=\"learnMore\">
+#   link: <html:a href=\"https://example.org\">
+#   img: <html:img src=\"icon.png\"/>
+# TODO - Translations can't set these attributes of the markup, so they were removed:
+#   strong: class=\"x\"
+learn-label = Read <strong title=\"A &amp; B\">more</strong> from { -brand-short-name } in <a data-l10n-name=\"learn-more\">the docs</a> &amp; <a data-l10n-name=\"link\">the wiki</a>{ \"\\u00A0\" }<img data-l10n-name=\"img\"/> &lt;3
+"
+                .into(),
+                vec![]
+            )
+        );
+
+        // Text-level elements don't need anything in the DOM.
+        assert_eq!(
+            serialize("<!ENTITY warning \"<b>Warning:</b> it's &lt;b&gt;\">").0,
+            "warning = <b>Warning:</b> it's &lt;b>\n"
+        );
+
+        // Each plural form has the same elements in the DOM.
+        assert_eq!(
+            serialize_dtd(
+                Serializer::default().plurals(KeySelection::new().key("files")),
+                "<!ENTITY files \"<html:a href='#'>#1 file</html:a>;<html:a href='#'>#1 files</html:a> in <html:span class='x'>a folder</html:span>\">",
+            )
+            .0,
+            "# TODO - The markup needs these elements in the DOM, with a data-l10n-name:
+#   link: <html:a href=\"#\">
+# TODO - Translations can't set these attributes of the markup, so they were removed:
+#   span: class=\"x\"
+files =
+  { $count ->
+    [one] <a data-l10n-name=\"link\">{ $count } file</a>
+   *[other] <a data-l10n-name=\"link\">{ $count } files</a> in <span>a folder</span>
+  }
+"
+        );
     }
-    return text;
 }
@@ -0,0 +1,267 @@
+use crate::parser::{
+    cst::Cst,
+    dtd::dtd,
+    parse_complete,
+    properties::{printf_pattern, properties},
+    Diagnostic, Node, Pattern, PatternElement, Placeholder,
+};
+use std::{ffi::OsStr, path::Path, sync::Arc};
+
+/// How the strings of the built-in formats mark the places where other text is
+/// inserted.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PlaceholderSyntax {
+    /// References to other entities, like `&brandShortName;`.
+    EntityReference,
+    /// printf-style placeholders, like `%S` or `%1$S`.
+    Printf,
+}
+
+impl PlaceholderSyntax {
+    /// Find the placeholders in the text of a value. Entity references are already
+    /// found by the DTD parser, as they are decoded along with the other XML references.
+    fn parse(self, pattern: &Pattern) -> Pattern {
+        match self {
+            PlaceholderSyntax::EntityReference => pattern.clone(),
+            PlaceholderSyntax::Printf => {
+                let mut parsed = Pattern::default();
+                // The placeholders without an explicit position keep counting across
+                // the pieces of text.
+                let mut previous_positions = 0;
+                for element in &pattern.elements {
+                    let text = match element {
+                        PatternElement::Text(text) => text,
+                        element => {
+                            parsed.push(element.clone());
+                            continue;
+                        }
+                    };
+                    let mut positions = 0;
+                    for element in printf_pattern(text).elements {
+                        match element {
+                            PatternElement::Placeholder(placeholder)
+                                if !placeholder.source.contains('$') =>
+                            {
+                                positions += 1;
+                                parsed.push(PatternElement::Placeholder(Placeholder {
+                                    position: placeholder.position + previous_positions,
+                                    ..placeholder
+                                }));
+                            }
+                            element => parsed.push(element),
+                        }
+                    }
+                    previous_positions += positions;
+                }
+                parsed
+            }
+        }
+    }
+}
+
+/// A format that can be migrated to Fluent. Implement this to add support for a new
+/// format, and register it with `Formats::register` or `Migrator::register_format`.
+pub trait SourceFormat: Send + Sync {
+    /// The name of the format, used to choose it explicitly.
+    fn name(&self) -> &'static str;
+
+    /// The file extensions used by the format, without the leading dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Check if the text of a file looks like this format. This is used for files
+    /// whose extension doesn't match any format.
+    fn sniff(&self, source: &str) -> bool;
+
+    /// Parse the source into nodes, recovering from errors. Every part of the source
+    /// that couldn't be parsed should be reported as a diagnostic.
+    fn parse<'a>(&self, source: &'a str) -> (Vec<Node<'a>>, Vec<Diagnostic>);
+
+    /// Find the placeholders in the parsed value of a message, with the syntax of the
+    /// format's strings, so that they become variables. This runs before the message is
+    /// migrated. By default the value is kept as it was parsed.
+    fn parse_placeholders(&self, pattern: &Pattern) -> Pattern {
+        pattern.clone()
+    }
+
+    /// Parse the source into a lossless syntax tree, that can reproduce the source
+    /// byte-for-byte. By default this is built from the spans of the parsed nodes.
+    fn parse_cst<'a>(&self, source: &'a str) -> (Cst<'a>, Vec<Diagnostic>) {
+        let (nodes, diagnostics) = self.parse(source);
+        (Cst::new(source, nodes), diagnostics)
+    }
+}
+
+/// Firefox .dtd files, with `<!ENTITY key "value">` declarations.
+#[derive(Debug, Clone, Copy)]
+pub struct Dtd;
+
+impl SourceFormat for Dtd {
+    fn name(&self) -> &'static str {
+        "dtd"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["dtd"]
+    }
+
+    fn sniff(&self, source: &str) -> bool {
+        let source = source.trim_start();
+        // Any declaration, comment or conditional section starts with `<!`.
+        source.starts_with("<!") || source.starts_with("<?xml")
+    }
+
+    fn parse<'a>(&self, source: &'a str) -> (Vec<Node<'a>>, Vec<Diagnostic>) {
+        parse_complete(dtd, source)
+    }
+
+    fn parse_placeholders(&self, pattern: &Pattern) -> Pattern {
+        PlaceholderSyntax::EntityReference.parse(pattern)
+    }
+}
+
+/// Java-style .properties files, with `key = value` lines.
+#[derive(Debug, Clone, Copy)]
+pub struct Properties;
+
+impl SourceFormat for Properties {
+    fn name(&self) -> &'static str {
+        "properties"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["properties"]
+    }
+
+    fn sniff(&self, source: &str) -> bool {
+        // Look at the first line that isn't blank or a comment.
+        match source
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with(['#', '!']))
+        {
+            Some(line) => !line.starts_with('<') && line.contains(['=', ':']),
+            None => false,
+        }
+    }
+
+    fn parse<'a>(&self, source: &'a str) -> (Vec<Node<'a>>, Vec<Diagnostic>) {
+        parse_complete(properties, source)
+    }
+
+    fn parse_placeholders(&self, pattern: &Pattern) -> Pattern {
+        PlaceholderSyntax::Printf.parse(pattern)
+    }
+}
+
+/// The set of formats that files can be migrated from.
+#[derive(Clone)]
+pub struct Formats {
+    formats: Vec<Arc<dyn SourceFormat>>,
+}
+
+impl Default for Formats {
+    /// The built-in .dtd and .properties formats.
+    fn default() -> Self {
+        Formats {
+            formats: vec![Arc::new(Dtd), Arc::new(Properties)],
+        }
+    }
+}
+
+impl std::fmt::Debug for Formats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl Formats {
+    /// Add a format. Formats that are registered later take precedence when detecting
+    /// the format of a file, so that the built-in formats can be replaced.
+    pub fn register(&mut self, format: impl SourceFormat + 'static) {
+        self.formats.insert(0, Arc::new(format));
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.formats.iter().map(|format| format.name()).collect()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&dyn SourceFormat> {
+        self.formats
+            .iter()
+            .find(|format| format.name() == name)
+            .map(|format| format.as_ref())
+    }
+
+    pub fn by_extension(&self, path: &Path) -> Option<&dyn SourceFormat> {
+        let extension = path.extension().and_then(OsStr::to_str)?;
+        self.formats
+            .iter()
+            .find(|format| format.extensions().contains(&extension))
+            .map(|format| format.as_ref())
+    }
+
+    /// Detect the format of a file from its extension, or failing that, from its
+    /// contents.
+    pub fn detect(&self, path: &Path, source: &str) -> Option<&dyn SourceFormat> {
+        self.by_extension(path).or_else(|| {
+            self.formats
+                .iter()
+                .find(|format| format.sniff(source))
+                .map(|format| format.as_ref())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert!(Dtd.sniff("\n<!ENTITY back \"Back\">"));
+        assert!(Dtd.sniff("<!-- License -->\n<!ENTITY back \"Back\">"));
+        assert!(Dtd.sniff("<?xml version=\"1.0\"?>\n<!ENTITY back \"Back\">"));
+        assert!(Dtd.sniff("<!ELEMENT window ANY>"));
+        assert!(!Dtd.sniff("back = Back"));
+
+        assert!(Properties.sniff("# License\n\nback = Back"));
+        assert!(Properties.sniff("! Comment\nback: Back"));
+        assert!(!Properties.sniff("<!ENTITY back \"Back\">"));
+        assert!(!Properties.sniff("Just some text"));
+        assert!(!Properties.sniff("# Only a comment"));
+    }
+
+    #[test]
+    fn test_placeholder_syntax() {
+        let mut pattern = Pattern::from("%S of %2$S &");
+        pattern.push_reference("brandShortName");
+        pattern.push_text("; in %S");
+        let positions = |pattern: Pattern| -> Vec<(usize, String)> {
+            pattern
+                .placeholders()
+                .map(|placeholder| (placeholder.position, placeholder.source.clone()))
+                .collect()
+        };
+
+        assert_eq!(
+            positions(PlaceholderSyntax::Printf.parse(&pattern)),
+            [(1, "%S".into()), (2, "%2$S".into()), (2, "%S".into())]
+        );
+        assert_eq!(PlaceholderSyntax::EntityReference.parse(&pattern), pattern);
+    }
+
+    #[test]
+    fn test_detect() {
+        let formats = Formats::default();
+        let name = |path: &str, source: &str| {
+            formats
+                .detect(Path::new(path), source)
+                .map(|format| format.name())
+        };
+        assert_eq!(name("browser.dtd", "back = Back"), Some("dtd"));
+        assert_eq!(name("browser.properties", ""), Some("properties"));
+        assert_eq!(name("browser.inc", "<!ENTITY back \"Back\">"), Some("dtd"));
+        assert_eq!(name("browser.inc", "back = Back"), Some("properties"));
+        assert_eq!(name("notes.txt", "Just some text"), None);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::format::{Dtd, SourceFormat};
+use crate::input::Input;
+use crate::parser::{Diagnostic, Node, Pattern, PatternElement};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// A DTD that is included by another file, along with the DTDs that it includes in
+/// turn.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncludedDtd {
+    /// The URL of the DTD, as it was written in the parameter entity.
+    pub url: String,
+    /// The file the URL was resolved to, if it could be found.
+    pub path: Option<PathBuf>,
+    pub includes: Vec<IncludedDtd>,
+}
+
+/// A checkout of the source code, like mozilla-central, that included DTDs are loaded
+/// from.
+#[derive(Debug, Clone, Default)]
+pub struct SourceRoot {
+    path: PathBuf,
+    /// The paths of the DTD files in the source root, by their file name. It's built
+    /// the first time a file is looked up.
+    index: OnceLock<HashMap<String, Vec<PathBuf>>>,
+}
+
+impl SourceRoot {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SourceRoot {
+            path: path.into(),
+            index: OnceLock::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Find the file for the URL of a DTD, like `chrome://branding/locale/brand.dtd`.
+    /// The chrome registry isn't available, so this looks for a file with the same name,
+    /// preferring the en-US locale files, and then the file whose path matches the most
+    /// of the URL. Ties are broken by the order of the paths, so the result is stable.
+    pub fn find(&self, url: &str) -> Option<PathBuf> {
+        let url_path: Vec<&str> = url.split('/').filter(|part| !part.is_empty()).collect();
+        let file_name = url_path.last()?;
+        let candidates = self.index().get(*file_name)?;
+        candidates
+            .iter()
+            .max_by_key(|path| {
+                let components: Vec<&str> = path
+                    .iter()
+                    .filter_map(|component| component.to_str())
+                    .collect();
+                let is_en_us = components
+                    .windows(2)
+                    .any(|pair| pair == ["locales", "en-US"]);
+                let matching = components
+                    .iter()
+                    .rev()
+                    .zip(url_path.iter().rev())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                // Reverse the path, so that the first path wins a tie.
+                (is_en_us, matching, std::cmp::Reverse(*path))
+            })
+            .cloned()
+    }
+
+    fn index(&self) -> &HashMap<String, Vec<PathBuf>> {
+        self.index.get_or_init(|| {
+            let mut index: HashMap<String, Vec<PathBuf>> = HashMap::new();
+            let mut directories = vec![self.path.clone()];
+            while let Some(directory) = directories.pop() {
+                let entries = match fs::read_dir(&directory) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if name.starts_with('.') {
+                        // Skip the version control directories.
+                        continue;
+                    }
+                    if path.is_dir() {
+                        directories.push(path);
+                    } else if name.ends_with(".dtd") {
+                        index.entry(name).or_default().push(path);
+                    }
+                }
+            }
+            for paths in index.values_mut() {
+                paths.sort();
+            }
+            index
+        })
+    }
+}
+
+/// The DTDs that a file includes, and the entities they declare.
+#[derive(Debug, Default)]
+pub struct Includes {
+    pub graph: Vec<IncludedDtd>,
+    /// The values of the entities, by their names. The first declaration of an entity
+    /// wins, like in XML.
+    pub entities: HashMap<String, Pattern>,
+    /// The included DTDs that couldn't be found.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Includes {
+    /// Load the DTDs that are included by the nodes, and the DTDs that those include.
+    /// Relative URLs are resolved against the directory of the including file, and
+    /// other URLs are looked up in the source root.
+    pub fn resolve(nodes: &[Node], path: Option<&Path>, root: Option<&SourceRoot>) -> Self {
+        let mut includes = Includes::default();
+        let mut visited = HashSet::new();
+        if let Some(path) = path {
+            visited.insert(path.to_path_buf());
+        }
+        includes.graph = includes.load(nodes, path, root, &mut visited, true);
+        includes
+    }
+
+    fn load(
+        &mut self,
+        nodes: &[Node],
+        path: Option<&Path>,
+        root: Option<&SourceRoot>,
+        visited: &mut HashSet<PathBuf>,
+        is_source: bool,
+    ) -> Vec<IncludedDtd> {
+        let mut graph = Vec::new();
+        for node in nodes {
+            let include = match node {
+                Node::Include(include) if include.referenced => include,
+                Node::Message(message) if !is_source => {
+                    self.entities
+                        .entry(message.key.to_string())
+                        .or_insert_with(|| message.value.clone());
+                    continue;
+                }
+                _ => continue,
+            };
+            let is_relative = !include.url.contains("://");
+            let include_path = if is_relative {
+                path.and_then(Path::parent)
+                    .map(|directory| directory.join(&include.url))
+                    .filter(|path| path.is_file())
+            } else {
+                root.and_then(|root| root.find(&include.url))
+            };
+            if include_path.is_none() && is_source && (is_relative || root.is_some()) {
+                self.warnings.push(Diagnostic {
+                    message: format!("Unable to find the included DTD {}", include.url),
+                    position: include.span.start,
+                });
+            }
+
+            let mut included = IncludedDtd {
+                url: include.url.clone(),
+                path: include_path,
+                includes: Vec::new(),
+            };
+            if let Some(include_path) = &included.path {
+                if visited.insert(include_path.clone()) {
+                    if let Ok(bytes) = fs::read(include_path) {
+                        let input = Input::from_bytes(&bytes);
+                        let (nodes, _) = Dtd.parse(&input.text);
+                        included.includes =
+                            self.load(&nodes, Some(include_path), root, visited, false);
+                    }
+                }
+            }
+            graph.push(included);
+        }
+        graph
+    }
+
+    /// Replace the references to the included entities with their values. The
+    /// references for which `keep` is true are left alone, like the ones that become
+    /// Fluent terms.
+    pub fn inline(&self, pattern: &Pattern, keep: impl Fn(&str) -> bool) -> Pattern {
+        let mut inlined = Pattern::default();
+        for element in &pattern.elements {
+            match element {
+                PatternElement::EntityReference(name) if !keep(name) => {
+                    match self.entities.get(name) {
+                        Some(value) => {
+                            for element in &value.elements {
+                                inlined.push(element.clone());
+                            }
+                        }
+                        None => inlined.push_reference(name),
+                    }
+                }
+                element => inlined.push(element.clone()),
+            }
+        }
+        inlined
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_inline() {
+        let mut includes = Includes::default();
+        includes
+            .entities
+            .insert("appName".into(), Pattern::from("Firefox"));
+        let mut pattern = Pattern::from("About ");
+        pattern.push_reference("appName");
+        pattern.push_text(" by ");
+        pattern.push_reference("brandShortName");
+        pattern.push_reference("unknown");
+
+        let mut expected = Pattern::from("About Firefox by ");
+        expected.push_reference("brandShortName");
+        expected.push_reference("unknown");
+        assert_eq!(
+            includes.inline(&pattern, |name| name == "brandShortName"),
+            expected
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use crate::parser::Diagnostic;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The character sets that source files are read in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// The historic encoding of .properties files. It's only used when a file isn't
+    /// valid UTF-8.
+    Latin1,
+}
+
+/// How a source file was encoded, so that it can be written back the same way.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Encoding {
+    pub charset: Charset,
+    /// The file started with a UTF-8 byte order mark.
+    pub bom: bool,
+}
+
+impl Encoding {
+    /// Encode text in the same way as the original file. The line endings are written
+    /// as they are, see `Input::original_text`. Characters that ISO-8859-1 can't
+    /// represent are written as `?`.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len() + BOM.len());
+        match self.charset {
+            Charset::Utf8 => {
+                if self.bom {
+                    bytes.extend_from_slice(BOM);
+                }
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Charset::Latin1 => bytes.extend(
+                text.chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?')),
+            ),
+        }
+        bytes
+    }
+}
+
+/// The text of a source file, cleaned up so that the parsers only ever see UTF-8 with
+/// `\n` line endings.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Input {
+    pub text: String,
+    pub encoding: Encoding,
+    /// The offsets in the text of the line breaks that were `\r\n` or `\r` in the
+    /// file, along with what they were. Files can mix their line endings, so they are
+    /// kept for every line.
+    pub line_endings: Vec<(usize, &'static str)>,
+    /// Problems with the encoding that are worth a manual review of the migration.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Input {
+    /// Decode the bytes of a file. The byte order mark is removed, line endings are
+    /// normalized to `\n`, and files that aren't valid UTF-8 are read as ISO-8859-1
+    /// with a warning.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut encoding = Encoding::default();
+        let bytes = match bytes.strip_prefix(BOM) {
+            Some(bytes) => {
+                encoding.bom = true;
+                bytes
+            }
+            None => bytes,
+        };
+        let (bytes, mut line_endings) = normalize_line_endings(bytes);
+
+        match String::from_utf8(bytes.into_owned()) {
+            Ok(text) => Input {
+                text,
+                encoding,
+                line_endings,
+                warnings: Vec::new(),
+            },
+            Err(error) => {
+                let valid_up_to = error.utf8_error().valid_up_to();
+                let bytes = error.into_bytes();
+                let text: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+                let offset: usize = bytes[..valid_up_to]
+                    .iter()
+                    .map(|&byte| char::from(byte).len_utf8())
+                    .sum();
+                let warnings = vec![Diagnostic::new(
+                    &text,
+                    offset,
+                    "The file is not valid UTF-8 from here, so it was read as ISO-8859-1. Check that the migrated text is correct."
+                        .into(),
+                )];
+                encoding.charset = Charset::Latin1;
+                // The bytes above 0x7F take two bytes in UTF-8, which moves the offsets of
+                // the line breaks.
+                let mut offset = 0;
+                let mut shift = 0;
+                for (line_break, _) in &mut line_endings {
+                    shift += bytes[offset..*line_break]
+                        .iter()
+                        .filter(|&&byte| byte > 0x7F)
+                        .count();
+                    offset = *line_break;
+                    *line_break += shift;
+                }
+                Input {
+                    text,
+                    encoding,
+                    line_endings,
+                    warnings,
+                }
+            }
+        }
+    }
+}
+
+impl Input {
+    /// The text of ranges of the normalized text, with the line endings that they had in
+    /// the file.
+    pub fn original_text(&self, ranges: impl IntoIterator<Item = Range<usize>>) -> String {
+        let mut text = String::new();
+        for range in ranges {
+            let first = self
+                .line_endings
+                .partition_point(|(offset, _)| *offset < range.start);
+            let mut start = range.start;
+            for (offset, ending) in &self.line_endings[first..] {
+                if *offset >= range.end {
+                    break;
+                }
+                text.push_str(&self.text[start..*offset]);
+                text.push_str(ending);
+                start = offset + 1;
+            }
+            text.push_str(&self.text[start..range.end]);
+        }
+        text
+    }
+}
+
+impl From<&str> for Input {
+    /// Clean up text that has already been decoded.
+    fn from(source: &str) -> Self {
+        Input::from_bytes(source.as_bytes())
+    }
+}
+
+/// Replace the `\r\n` and `\r` line endings with `\n`, and list where they were.
+fn normalize_line_endings(bytes: &[u8]) -> (Cow<'_, [u8]>, Vec<(usize, &'static str)>) {
+    if !bytes.contains(&b'\r') {
+        return (Cow::from(bytes), Vec::new());
+    }
+    let mut normalized = Vec::with_capacity(bytes.len());
+    let mut line_endings = Vec::new();
+    let mut iter = bytes.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        if byte == b'\r' {
+            let ending = match iter.next_if_eq(&&b'\n') {
+                Some(_) => "\r\n",
+                None => "\r",
+            };
+            line_endings.push((normalized.len(), ending));
+            normalized.push(b'\n');
+        } else {
+            normalized.push(byte);
+        }
+    }
+    (Cow::from(normalized), line_endings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_utf8() {
+        let input = Input::from_bytes("key = Café\n".as_bytes());
+        assert_eq!(input.text, "key = Café\n");
+        assert_eq!(input.encoding, Encoding::default());
+        assert_eq!(input.warnings, []);
+    }
+
+    #[test]
+    fn test_bom() {
+        let input = Input::from_bytes(b"\xEF\xBB\xBFkey = Value\n");
+        assert_eq!(input.text, "key = Value\n");
+        assert!(input.encoding.bom);
+        assert_eq!(
+            input.encoding.encode(&input.text),
+            b"\xEF\xBB\xBFkey = Value\n"
+        );
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let input = Input::from("one\r\ntwo\rthree\n\r\nfour");
+        assert_eq!(input.text, "one\ntwo\nthree\n\nfour");
+        assert_eq!(input.line_endings, [(3, "\r\n"), (7, "\r"), (14, "\r\n")]);
+        assert_eq!(
+            input.original_text(Some(0..input.text.len())),
+            "one\r\ntwo\rthree\n\r\nfour"
+        );
+        assert_eq!(input.original_text(vec![4..8, 13..15]), "two\r\n\r\n");
+        assert_eq!(Input::from("one\ntwo").line_endings, []);
+    }
+
+    #[test]
+    fn test_latin1() {
+        let input = Input::from_bytes(b"first = First\r\nsecond = Caf\xE9\r\n");
+        assert_eq!(input.text, "first = First\nsecond = Café\n");
+        assert_eq!(input.encoding.charset, Charset::Latin1);
+        assert_eq!(input.warnings.len(), 1);
+        assert_eq!(input.warnings[0].position.line, 2);
+        assert_eq!(input.warnings[0].position.column, 13);
+        // The é takes two bytes in the text.
+        assert_eq!(input.line_endings, [(13, "\r\n"), (28, "\r\n")]);
+        assert_eq!(
+            input
+                .encoding
+                .encode(&input.original_text(Some(14..input.text.len()))),
+            b"second = Caf\xE9\r\n".to_vec()
+        );
+        assert_eq!(
+            input.encoding.encode("second = Café €\r\n"),
+            b"second = Caf\xE9 ?\r\n".to_vec()
+        );
+    }
+}
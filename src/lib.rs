@@ -0,0 +1,60 @@
+//! Migrate Firefox .dtd and .properties files to Fluent.
+//!
+//! The simplest way to use this is through a `Migrator`, which holds the options for a
+//! migration:
+//!
+//! ```
+//! use fluent_migrator::{Dtd, Migrator};
+//!
+//! let migration = Migrator::new()
+//!     .migrate("<!ENTITY ldb.BackButton.label \"Back\">", &Dtd)
+//!     .unwrap();
+//! assert!(migration.fluent.contains("ldb-back-button-label = Back"));
+//! ```
+//!
+//! New formats can be supported by implementing `SourceFormat`. The parsers and the
+//! Fluent serializer are available on their own as well.
+
+pub mod error;
+pub mod fluent;
+pub mod format;
+pub mod includes;
+pub mod input;
+pub mod migrator;
+pub mod parser;
+pub mod plural;
+pub mod selection;
+
+pub use error::Error;
+pub use fluent::{nodes_to_fluent, Serializer};
+pub use format::{Dtd, Formats, Properties, SourceFormat};
+pub use includes::{IncludedDtd, SourceRoot};
+pub use input::{Charset, Encoding, Input};
+pub use migrator::{Migration, Migrator};
+pub use parser::cst::{Cst, Element};
+pub use parser::{
+    Comment, Diagnostic, Include, Junk, Message, Node, NoteTarget, Pattern, PatternElement,
+    Placeholder, Position, Span,
+};
+pub use selection::KeySelection;
+
+/// Parse the source of a .dtd file, failing if any part of it couldn't be parsed.
+pub fn parse_dtd(source: &str) -> Result<Vec<Node<'_>>, Error> {
+    parser::parse(parser::dtd::dtd, source)
+}
+
+/// Parse the source of a .properties file, failing if any part of it couldn't be
+/// parsed. The printf-style placeholders of the values are parsed too.
+pub fn parse_properties(source: &str) -> Result<Vec<Node<'_>>, Error> {
+    let nodes = parser::parse(parser::properties::properties, source)?;
+    Ok(nodes
+        .into_iter()
+        .map(|node| match node {
+            Node::Message(message) => Node::Message(Message {
+                value: Properties.parse_placeholders(&message.value),
+                ..message
+            }),
+            node => node,
+        })
+        .collect())
+}
@@ -1,31 +1,28 @@
-#![allow(unused_imports)]
-
-pub mod fluent;
-#[macro_use]
-pub mod parser;
-
 use clap::{App, Arg};
-use fluent::nodes_to_fluent;
-use parser::dtd::dtd;
-use parser::properties::properties;
-use std::{
-    ffi::OsStr,
-    fs,
-    path::{Path, PathBuf},
-};
+use fluent_migrator::{Error, Formats, IncludedDtd, KeySelection, Migrator};
+use std::{path::Path, process};
 
 struct ParsedArgs<'a> {
     files: Vec<&'a str>,
     save: bool,
     overwrite: bool,
-}
-
-enum Extension {
-    Dtd,
-    Properties,
+    allow_partial: bool,
+    input_format: Option<&'a str>,
+    keys: Vec<&'a str>,
+    key_prefixes: Vec<&'a str>,
+    key_patterns: Vec<&'a str>,
+    terms: Vec<&'a str>,
+    variables: Vec<&'a str>,
+    plurals: Vec<&'a str>,
+    source_locale: Option<&'a str>,
+    attributes: Option<Vec<&'a str>>,
+    unique_ids: bool,
+    source_root: Option<&'a str>,
+    verbose: bool,
 }
 
 fn main() {
+    let format_names = Formats::default().names();
     let matches = App::new("Fluent Migrator")
         .version("v1.0.0")
         .about(
@@ -51,6 +48,34 @@ To update this utility run the following command:
 
 - Migrate multiple files at once
   fluent-migrator --save file1.dtd file2.properties file3.dtd
+
+- Migrate as much as possible of a file that can't be fully parsed
+  fluent-migrator --allow-partial path/to/file.dtd
+
+- Migrate a file with an unusual extension
+  fluent-migrator --input-format dtd path/to/file.inc
+
+- Name the variables of the placeholders in a .properties file, like %1$S and %2$S
+  fluent-migrator --variables host,port path/to/file.properties
+
+- Convert lists of plural forms, like #1 file;#1 files, into select expressions
+  fluent-migrator --plurals downloads.files path/to/file.properties
+  fluent-migrator --source-locale fr path/to/file.properties
+
+- Choose the keys that become attributes, like save.label and save.accesskey, or none
+  fluent-migrator --attributes label,accesskey,tooltiptext path/to/file.dtd
+  fluent-migrator --attributes '' path/to/file.dtd
+
+- Keep the IDs unique across files whose .ftl files are loaded together
+  fluent-migrator --unique-ids path/to/first.dtd path/to/second.properties
+
+- Load the DTDs that a file includes from a mozilla-central checkout
+  fluent-migrator --source-root ~/dev/mozilla-central --verbose path/to/file.dtd
+
+- Migrate some of the keys, and remove them from the original file
+  fluent-migrator --save --keys back.label,forward.label path/to/file.dtd
+  fluent-migrator --save --key-prefix downloads. path/to/file.properties
+  fluent-migrator --save --key-regex '^download[0-9]+$' path/to/file.properties
 ",
         )
         .arg(
@@ -66,73 +91,276 @@ To update this utility run the following command:
         .arg(Arg::from_usage(
             "--overwrite... 'Overwrite an .ftl file if it already exists'",
         ))
+        .arg(Arg::from_usage(
+            "--allow-partial... 'Output the migration even if part of the file could not be parsed'",
+        ))
+        .arg(
+            Arg::from_usage(
+                "--input-format [FORMAT] 'The format of the files, instead of detecting it from their extension or contents'",
+            )
+            .possible_values(&format_names),
+        )
+        .arg(
+            Arg::from_usage(
+                "--keys [KEYS]... 'Only migrate these comma separated keys. With --save they are removed from the original file, and appended to the .ftl file'",
+            )
+            .use_delimiter(true)
+            .require_delimiter(true),
+        )
+        .arg(
+            Arg::from_usage(
+                "--key-prefix [PREFIX]... 'Only migrate the keys that start with the prefix, like --keys'",
+            )
+            .number_of_values(1),
+        )
+        .arg(
+            Arg::from_usage(
+                "--key-regex [REGEX]... 'Only migrate the keys that match the regular expression, like --keys'",
+            )
+            .number_of_values(1),
+        )
+        .arg(
+            Arg::from_usage(
+                "--term [ENTITY=TERM]... 'Convert references to a DTD entity into references to a Fluent term, like --term brandShortName=-brand-short-name'",
+            )
+            .number_of_values(1),
+        )
+        .arg(
+            Arg::from_usage(
+                "--variables [NAMES]... 'The comma separated names of the variables for the placeholders like %S and %1$S, by position, when no LOCALIZATION NOTE names them'",
+            )
+            .use_delimiter(true)
+            .require_delimiter(true),
+        )
+        .arg(
+            Arg::from_usage(
+                "--plurals [KEYS]... 'The comma separated keys of lists of plural forms like \"#1 file;#1 files\", which are found from their LOCALIZATION NOTE without this'",
+            )
+            .use_delimiter(true)
+            .require_delimiter(true),
+        )
+        .arg(Arg::from_usage(
+            "--source-locale [LOCALE] 'The locale of the files, for the plural forms [default: en-US]'",
+        ))
+        .arg(
+            Arg::from_usage(
+                "--attributes [SUFFIXES]... 'The comma separated suffixes of the keys that become attributes of one message, like save.label and save.accesskey [default: label,accesskey,title,tooltiptext,placeholder,value,aria-label,key,commandkey]'",
+            )
+            .use_delimiter(true)
+            .require_delimiter(true),
+        )
+        .arg(Arg::from_usage(
+            "--unique-ids 'Give the messages of all of the files different IDs, as their .ftl files are loaded together'",
+        ))
+        .arg(Arg::from_usage(
+            "--source-root [DIR] 'Load the DTDs that are included by the files from this checkout of the source code'",
+        ))
+        .arg(Arg::from_usage(
+            "-v, --verbose 'Report the DTDs that are included by each file'",
+        ))
         .get_matches();
 
     let args = ParsedArgs {
         files: matches
             .values_of("files")
-            .expect("At least one dtd file must be provided.")
-            .collect(),
+            .map(|files| files.collect())
+            .unwrap_or_default(),
         save: matches.is_present("save"),
         overwrite: matches.is_present("overwrite"),
+        allow_partial: matches.is_present("allow-partial"),
+        input_format: matches.value_of("input-format"),
+        keys: matches
+            .values_of("keys")
+            .map(|keys| keys.collect())
+            .unwrap_or_default(),
+        key_prefixes: matches
+            .values_of("key-prefix")
+            .map(|prefixes| prefixes.collect())
+            .unwrap_or_default(),
+        key_patterns: matches
+            .values_of("key-regex")
+            .map(|patterns| patterns.collect())
+            .unwrap_or_default(),
+        terms: matches
+            .values_of("term")
+            .map(|terms| terms.collect())
+            .unwrap_or_default(),
+        variables: matches
+            .values_of("variables")
+            .map(|variables| variables.collect())
+            .unwrap_or_default(),
+        plurals: matches
+            .values_of("plurals")
+            .map(|plurals| plurals.collect())
+            .unwrap_or_default(),
+        source_locale: matches.value_of("source-locale"),
+        attributes: matches
+            .values_of("attributes")
+            .map(|attributes| attributes.collect()),
+        unique_ids: matches.is_present("unique-ids"),
+        source_root: matches.value_of("source-root"),
+        verbose: matches.is_present("verbose"),
     };
 
-    let files_len = args.files.len();
-    for path_str in args.files {
-        let path = Path::new(path_str);
-        if !path.is_file() {
-            println!("File not found: {}", path_str);
-            continue;
+    let mut migrator = Migrator::new()
+        .allow_partial(args.allow_partial)
+        .overwrite(args.overwrite);
+    if let Some(input_format) = args.input_format {
+        migrator = migrator.input_format(input_format);
+    }
+    if let Some(source_root) = args.source_root {
+        migrator = migrator.source_root(source_root);
+    }
+    for term in &args.terms {
+        match term.split_once('=') {
+            Some((entity, term)) => migrator = migrator.term(entity.trim(), term.trim()),
+            None => {
+                eprintln!("Error: Expected --term to be ENTITY=TERM, got \"{}\"", term);
+                process::exit(1);
+            }
         }
-        let extension = match path.extension() {
-            Some(extension) => {
-                if extension == OsStr::new("dtd") {
-                    Some(Extension::Dtd)
-                } else if extension == OsStr::new("properties") {
-                    Some(Extension::Properties)
-                } else {
-                    None
-                }
+    }
+    for variable in &args.variables {
+        migrator = migrator.fallback_variable(variable.trim().trim_start_matches('$'));
+    }
+    if !args.plurals.is_empty() {
+        let plurals = args
+            .plurals
+            .iter()
+            .fold(KeySelection::new(), |selection, key| {
+                selection.key(key.trim())
+            });
+        migrator = migrator.plurals(plurals);
+    }
+    if let Some(locale) = args.source_locale {
+        migrator = match migrator.source_locale(locale) {
+            Ok(migrator) => migrator,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                process::exit(1);
             }
-            None => None,
         };
+    }
+    if let Some(attributes) = &args.attributes {
+        migrator = migrator.attributes(
+            attributes
+                .iter()
+                .map(|attribute| attribute.trim())
+                .filter(|attribute| !attribute.is_empty()),
+        );
+    }
+    match key_selection(&args) {
+        Ok(Some(selection)) => migrator = migrator.select_keys(selection),
+        Ok(None) => {}
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        }
+    }
 
-        if let Some(extension) = extension {
-            let string = fs::read_to_string(path).expect("Failed to read file.");
-            let nodes = match extension {
-                Extension::Dtd => parse!(dtd, &string).1,
-                Extension::Properties => parse!(properties, &string).1,
-            };
-            let fluent_text = nodes_to_fluent(&nodes);
-            if args.save {
-                let mut save_path = PathBuf::from(path);
-                assert!(save_path.set_extension("ftl"));
-                if save_path.is_file() && !args.overwrite {
-                    // The file exists, warn but don't overwrite.
-                    println!(
-                        "Skipping file as it exists, use --overwrite to replace: {}",
-                        save_path.display()
-                    );
-                    continue;
+    let mut failures = 0;
+    for path_str in &args.files {
+        match migrate(&migrator, Path::new(path_str), &args) {
+            // The next files can't use the IDs of this one.
+            Ok(ids) if args.unique_ids => migrator = migrator.reserve_ids(ids),
+            Ok(_) => {}
+            Err(error) => {
+                failures += 1;
+                eprintln!("Error: {}", error);
+                match error {
+                    Error::Parse { .. } => {
+                        eprintln!("Use --allow-partial to migrate the rest of the file anyway.")
+                    }
+                    Error::OutputConflict(_) => eprintln!("Use --overwrite to replace it."),
+                    Error::UnsupportedExtension(_) => {
+                        eprintln!("Use --input-format to choose the format of the file.")
+                    }
+                    _ => {}
                 }
-                match fs::write(save_path.clone(), fluent_text) {
-                    Ok(_) => println!("Saved: {}", save_path.display()),
-                    Err(err) => println!("Failed to write: {}\n{}", save_path.display(), err),
-                };
-            } else {
-                if files_len > 1 {
-                    println!(
-                        "\nConverting: {}\n===================================================================\n",
-                        path.display()
-                    );
-                }
-                println!("{}", fluent_text);
             }
-        } else {
+        }
+    }
+
+    if failures > 0 {
+        eprintln!(
+            "\nFailed to migrate {} of {} files.",
+            failures,
+            args.files.len()
+        );
+        process::exit(1);
+    }
+}
+
+/// Build the selection of keys to migrate, if any were chosen.
+fn key_selection(args: &ParsedArgs) -> Result<Option<KeySelection>, Error> {
+    if args.keys.is_empty() && args.key_prefixes.is_empty() && args.key_patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut selection = KeySelection::new();
+    for key in &args.keys {
+        selection = selection.key(key.trim());
+    }
+    for prefix in &args.key_prefixes {
+        selection = selection.prefix(*prefix);
+    }
+    for pattern in &args.key_patterns {
+        selection = selection.pattern(pattern)?;
+    }
+    Ok(Some(selection))
+}
+
+/// Migrate a file, and return the IDs of its messages.
+fn migrate(migrator: &Migrator, path: &Path, args: &ParsedArgs) -> Result<Vec<String>, Error> {
+    let migration = migrator.migrate_file(path)?;
+    for diagnostic in migration.diagnostics.iter().chain(&migration.warnings) {
+        eprintln!("Warning: {}:{}", path.display(), diagnostic);
+    }
+    if args.verbose && !migration.includes.is_empty() {
+        eprintln!("Includes: {}", path.display());
+        print_includes(&migration.includes, 1);
+    }
+    if migration.remaining_source.is_some() && migration.migrated_keys.is_empty() {
+        eprintln!(
+            "Warning: {}: None of the selected keys were found",
+            path.display()
+        );
+        return Ok(migration.ids);
+    }
+
+    if args.save {
+        let save_path = migrator.save(path, &migration)?;
+        println!("Saved: {}", save_path.display());
+        if migration.remaining_source.is_some() {
+            println!(
+                "Removed {} migrated keys from: {}",
+                migration.migrated_keys.len(),
+                path.display()
+            );
+        }
+    } else {
+        if args.files.len() > 1 {
             println!(
-                "The following file cannot be converted as it does not have a .dtd or .properties extension:\n{}",
-                path_str,
+                "\nConverting: {}\n===================================================================\n",
+                path.display()
             );
         }
+        println!("{}", migration.fluent);
+    }
+    Ok(migration.ids)
+}
+
+/// Print the include graph as an indented tree.
+fn print_includes(includes: &[IncludedDtd], depth: usize) {
+    for include in includes {
+        match &include.path {
+            Some(path) => eprintln!(
+                "{}{} -> {}",
+                "  ".repeat(depth),
+                include.url,
+                path.display()
+            ),
+            None => eprintln!("{}{} (not found)", "  ".repeat(depth), include.url),
+        }
+        print_includes(&include.includes, depth + 1);
     }
 }
@@ -0,0 +1,321 @@
+use crate::error::Error;
+use crate::fluent::{fluent_ids, Serializer};
+use crate::format::{Formats, SourceFormat};
+use crate::includes::{IncludedDtd, Includes, SourceRoot};
+use crate::input::{Encoding, Input};
+use crate::parser::{Diagnostic, Message, Node};
+use crate::selection::{kept_ranges, message_keys, KeySelection};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The result of migrating a single file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Migration {
+    /// The text of the .ftl file.
+    pub fluent: String,
+    /// The messages of the .ftl file, without the license and the description that start
+    /// it. This is what `save` appends to an existing .ftl file.
+    pub messages: String,
+    /// The parts of the source that couldn't be migrated. These are only ever present
+    /// when partial migrations are allowed.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Problems that didn't stop the migration, but are worth a manual review.
+    pub warnings: Vec<Diagnostic>,
+    /// The keys of the messages that were migrated, in source order.
+    pub migrated_keys: Vec<String>,
+    /// The IDs of the messages of the .ftl file, in order.
+    pub ids: Vec<String>,
+    /// The source without the migrated messages, with the line endings of the original
+    /// file. This is only present for partial migrations of a key selection, where it
+    /// replaces the source file when saving.
+    pub remaining_source: Option<String>,
+    /// How the source was encoded. The remaining source is written back this way.
+    pub encoding: Encoding,
+    /// The DTDs that the source includes.
+    pub includes: Vec<IncludedDtd>,
+}
+
+/// Holds the options for migrating files to Fluent.
+///
+/// ```
+/// use fluent_migrator::Migrator;
+///
+/// let migrator = Migrator::new().allow_partial(true).overwrite(false);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Migrator {
+    allow_partial: bool,
+    overwrite: bool,
+    formats: Formats,
+    input_format: Option<String>,
+    selection: Option<KeySelection>,
+    serializer: Serializer,
+    source_root: Option<SourceRoot>,
+}
+
+impl Migrator {
+    pub fn new() -> Self {
+        Migrator::default()
+    }
+
+    /// Migrate a source even if part of it couldn't be parsed. The parts that couldn't
+    /// be parsed are reported in `Migration::diagnostics`, and kept as comments in the
+    /// .ftl for a manual review. By default this is an error.
+    pub fn allow_partial(mut self, allow_partial: bool) -> Self {
+        self.allow_partial = allow_partial;
+        self
+    }
+
+    /// Replace .ftl files that already exist when saving. By default this is an error.
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Add support for another format. It takes precedence over the existing formats
+    /// with the same extension.
+    pub fn register_format(mut self, format: impl SourceFormat + 'static) -> Self {
+        self.formats.register(format);
+        self
+    }
+
+    /// Treat every file as the format with this name, rather than detecting it.
+    pub fn input_format(mut self, name: impl Into<String>) -> Self {
+        self.input_format = Some(name.into());
+        self
+    }
+
+    /// Only migrate the messages in the selection. The migrated messages, and their
+    /// LOCALIZATION NOTE comments, are removed from the source file when saving, and the
+    /// .ftl file is appended to if it already exists. This makes it possible to migrate
+    /// a file in several steps.
+    pub fn select_keys(mut self, selection: KeySelection) -> Self {
+        self.selection = Some(selection);
+        self
+    }
+
+    /// Convert references to a DTD entity into references to a Fluent term. The common
+    /// Firefox terms like `&brandShortName;` are converted without this.
+    pub fn term(mut self, entity: impl Into<String>, term: &str) -> Self {
+        self.serializer = self.serializer.term(entity, term);
+        self
+    }
+
+    /// Name the variables of the printf-style placeholders that aren't named by a
+    /// LOCALIZATION NOTE, by position. See `Serializer::fallback_variable`.
+    pub fn fallback_variable(mut self, name: impl Into<String>) -> Self {
+        self.serializer = self.serializer.fallback_variable(name);
+        self
+    }
+
+    /// Convert the selected messages from lists of plural forms into select expressions,
+    /// even without a comment that says they are plural. See `Serializer::plurals`.
+    pub fn plurals(mut self, selection: KeySelection) -> Self {
+        self.serializer = self.serializer.plurals(selection);
+        self
+    }
+
+    /// Set the locale of the source files, which decides the plural categories of the
+    /// plural forms. The default is `en-US`.
+    pub fn source_locale(mut self, locale: &str) -> Result<Self, Error> {
+        self.serializer = self.serializer.source_locale(locale)?;
+        Ok(self)
+    }
+
+    /// Set the suffixes of the keys that become the attributes of a message, like
+    /// `label` and `accesskey`. See `Serializer::attributes`.
+    pub fn attributes<S: Into<String>>(mut self, suffixes: impl IntoIterator<Item = S>) -> Self {
+        self.serializer = self.serializer.attributes(suffixes);
+        self
+    }
+
+    /// Treat the IDs as used already, so that the messages get other IDs. This keeps
+    /// the IDs of a batch of .ftl files that are loaded together unique, by reserving
+    /// the `Migration::ids` of each file for the next ones. See
+    /// `Serializer::reserve_ids`.
+    pub fn reserve_ids<S: Into<String>>(mut self, ids: impl IntoIterator<Item = S>) -> Self {
+        self.serializer = self.serializer.reserve_ids(ids);
+        self
+    }
+
+    /// Load the DTDs that a file includes from a checkout of the source code, like
+    /// mozilla-central. References to the entities they declare are then replaced with
+    /// the values of the entities, unless they are Fluent terms. Relative includes are
+    /// loaded next to the file without this.
+    pub fn source_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.source_root = Some(SourceRoot::new(path));
+        self
+    }
+
+    pub fn formats(&self) -> &Formats {
+        &self.formats
+    }
+
+    /// Migrate the text of a source file.
+    pub fn migrate(&self, source: &str, format: &dyn SourceFormat) -> Result<Migration, Error> {
+        self.migrate_input(Input::from(source), format, None)
+    }
+
+    fn migrate_input(
+        &self,
+        input: Input,
+        format: &dyn SourceFormat,
+        path: Option<&Path>,
+    ) -> Result<Migration, Error> {
+        let (cst, diagnostics) = format.parse_cst(&input.text);
+        if !diagnostics.is_empty() && !self.allow_partial {
+            return Err(Error::Parse {
+                path: None,
+                diagnostics,
+            });
+        }
+        let includes = Includes::resolve(
+            &cst.nodes().cloned().collect::<Vec<_>>(),
+            path,
+            self.source_root.as_ref(),
+        );
+        // The IDs of the .ftl file that the migration is appended to are already taken.
+        let appending_serializer;
+        let serializer = match path.and_then(|path| self.appended_ids(path)) {
+            Some(ids) => {
+                appending_serializer = self.serializer.clone().reserve_ids(ids);
+                &appending_serializer
+            }
+            None => &self.serializer,
+        };
+        let keys = message_keys(&cst);
+        let source_nodes: Vec<&Node> = cst.nodes().collect();
+        let nodes: Vec<Node> = source_nodes
+            .iter()
+            .enumerate()
+            .filter(|(index, node)| match &self.selection {
+                Some(selection) => {
+                    let previous = index.checked_sub(1).map(|i| source_nodes[i]);
+                    let next = source_nodes.get(index + 1).copied();
+                    selection.migrates(node, previous, next, &keys)
+                }
+                None => true,
+            })
+            .map(|(_, &node)| match node {
+                Node::Message(message) => Node::Message(Message {
+                    value: format.parse_placeholders(
+                        &includes.inline(&message.value, |name| serializer.has_term(name)),
+                    ),
+                    ..message.clone()
+                }),
+                node => node.clone(),
+            })
+            .collect();
+        let migrated_keys = nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Message(message) => Some(message.key.to_string()),
+                _ => None,
+            })
+            .collect();
+        let ids = serializer
+            .message_ids(&nodes)
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        let (mut fluent, messages, serializer_warnings) = serializer.serialize_parts(&nodes);
+        fluent.push_str(&messages);
+        let remaining_source = self
+            .selection
+            .as_ref()
+            .map(|selection| input.original_text(kept_ranges(&cst, selection)));
+        let mut warnings = input.warnings;
+        warnings.extend(includes.warnings);
+        warnings.extend(serializer_warnings);
+        Ok(Migration {
+            fluent,
+            messages,
+            diagnostics,
+            warnings,
+            migrated_keys,
+            ids,
+            remaining_source,
+            encoding: input.encoding,
+            includes: includes.graph,
+        })
+    }
+
+    /// The IDs of the existing .ftl file that `save` appends the partial migration of
+    /// the file at `path` to, if there is one.
+    fn appended_ids(&self, path: &Path) -> Option<Vec<String>> {
+        if self.selection.is_none() || self.overwrite {
+            return None;
+        }
+        let fluent = fs::read_to_string(path.with_extension("ftl")).ok()?;
+        Some(fluent_ids(&fluent).into_iter().map(String::from).collect())
+    }
+
+    /// Read and migrate a file. The format is the input format if one was chosen, and
+    /// is otherwise detected from the file's extension or contents. Files that aren't
+    /// valid UTF-8 are read as ISO-8859-1, with a warning.
+    pub fn migrate_file(&self, path: impl AsRef<Path>) -> Result<Migration, Error> {
+        let path = path.as_ref();
+        let chosen_format = match &self.input_format {
+            Some(name) => Some(
+                self.formats
+                    .by_name(name)
+                    .ok_or_else(|| Error::UnknownFormat(name.clone()))?,
+            ),
+            None => self.formats.by_extension(path),
+        };
+        let bytes = fs::read(path).map_err(|error| Error::Io {
+            path: path.into(),
+            error,
+        })?;
+        let input = Input::from_bytes(&bytes);
+        let format = chosen_format
+            .or_else(|| self.formats.detect(path, &input.text))
+            .ok_or_else(|| Error::UnsupportedExtension(path.into()))?;
+        self.migrate_input(input, format, Some(path))
+            .map_err(|error| error.with_path(path))
+    }
+
+    /// Save a migration of the file at `path` next to it, with a .ftl extension. The
+    /// path of the saved file is returned.
+    ///
+    /// A partial migration of a key selection is appended to the .ftl file if it already
+    /// exists, and the migrated messages are removed from the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>, migration: &Migration) -> Result<PathBuf, Error> {
+        let path = path.as_ref();
+        let mut save_path = PathBuf::from(path);
+        if !save_path.set_extension("ftl") {
+            return Err(Error::InvalidPath(path.into()));
+        }
+        let io_error = |path: &Path| {
+            let path = PathBuf::from(path);
+            move |error| Error::Io { path, error }
+        };
+
+        match &migration.remaining_source {
+            Some(remaining_source) => {
+                if save_path.is_file() && !self.overwrite {
+                    // The file already has a header, so only append the messages.
+                    fs::OpenOptions::new()
+                        .append(true)
+                        .open(&save_path)
+                        .and_then(|mut file| write!(file, "\n{}", migration.messages.trim_start()))
+                        .map_err(io_error(&save_path))?;
+                } else {
+                    fs::write(&save_path, &migration.fluent).map_err(io_error(&save_path))?;
+                }
+                fs::write(path, migration.encoding.encode(remaining_source))
+                    .map_err(io_error(path))?;
+            }
+            None => {
+                if save_path.is_file() && !self.overwrite {
+                    return Err(Error::OutputConflict(save_path));
+                }
+                fs::write(&save_path, &migration.fluent).map_err(io_error(&save_path))?;
+            }
+        }
+        Ok(save_path)
+    }
+}
@@ -0,0 +1,185 @@
+use super::*;
+
+/// A lossless concrete syntax tree of a file. Every byte of the source belongs to
+/// exactly one element, in order, so the original file can be reproduced byte-for-byte
+/// with `to_string`. This makes it possible to rewrite parts of a file without
+/// disturbing the rest of it.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Cst<'a> {
+    pub elements: Vec<Element<'a>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Element<'a> {
+    /// Whitespace and blank lines between the nodes.
+    Whitespace(&'a str),
+    /// Text that the parser skipped without making a node, like the parameter entity
+    /// declarations in a DTD.
+    Ignored(&'a str),
+    /// A parsed node, along with its exact source text.
+    Node { text: &'a str, node: Node<'a> },
+}
+
+impl<'a> Element<'a> {
+    /// The exact source text of the element.
+    pub fn text(&self) -> &'a str {
+        match self {
+            Element::Whitespace(text) | Element::Ignored(text) => text,
+            Element::Node { text, .. } => text,
+        }
+    }
+}
+
+impl<'a> Cst<'a> {
+    /// Build the tree from the nodes that were parsed from the source. The text between
+    /// the spans of the nodes is kept as whitespace, or as ignored text. The nodes must
+    /// be in source order, and must not overlap.
+    pub fn new(source: &'a str, nodes: Vec<Node<'a>>) -> Self {
+        let mut elements = Vec::new();
+        let mut offset = 0;
+        for node in nodes {
+            let range = node.span().range();
+            debug_assert!(range.start >= offset, "The nodes overlap.");
+            Cst::push_gap(&mut elements, &source[offset..range.start]);
+            elements.push(Element::Node {
+                text: &source[range.clone()],
+                node,
+            });
+            offset = range.end;
+        }
+        Cst::push_gap(&mut elements, &source[offset..]);
+        Cst { elements }
+    }
+
+    /// Split the text between two nodes into the leading whitespace, anything that was
+    /// ignored, and the trailing whitespace.
+    fn push_gap(elements: &mut Vec<Element<'a>>, gap: &'a str) {
+        let is_whitespace = |c: char| " \t\r\n\x0C".contains(c);
+        let content = gap.trim_matches(is_whitespace);
+        if content.is_empty() {
+            if !gap.is_empty() {
+                elements.push(Element::Whitespace(gap));
+            }
+            return;
+        }
+        let start = gap.offset(content);
+        if start > 0 {
+            elements.push(Element::Whitespace(&gap[..start]));
+        }
+        elements.push(Element::Ignored(content));
+        if start + content.len() < gap.len() {
+            elements.push(Element::Whitespace(&gap[start + content.len()..]));
+        }
+    }
+
+    /// Iterate over the parsed nodes.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<'a>> {
+        self.elements.iter().filter_map(|element| match element {
+            Element::Node { node, .. } => Some(node),
+            _ => None,
+        })
+    }
+}
+
+impl<'a> std::fmt::Display for Cst<'a> {
+    /// Reproduce the source text.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for element in &self.elements {
+            f.write_str(element.text())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Describe the elements as (kind, text) pairs for easy comparison.
+    fn describe<'a>(cst: &Cst<'a>) -> Vec<(&'static str, &'a str)> {
+        cst.elements
+            .iter()
+            .map(|element| {
+                let kind = match element {
+                    Element::Whitespace(_) => "whitespace",
+                    Element::Ignored(_) => "ignored",
+                    Element::Node {
+                        node: Node::Message(_),
+                        ..
+                    } => "message",
+                    Element::Node {
+                        node: Node::Comment(_),
+                        ..
+                    } => "comment",
+                    Element::Node {
+                        node: Node::Junk(_),
+                        ..
+                    } => "junk",
+                    Element::Node {
+                        node: Node::Include(_),
+                        ..
+                    } => "include",
+                };
+                (kind, element.text())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dtd_round_trip() {
+        let source = "<!-- License -->\r\n\r\n<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">\n%brandDTD;\n\n  <!ENTITY back.label   \"Back\">\n<!UNKNOWN foo>\n<!ENTITY forward.label \"Forward\">";
+        let (_, nodes) = dtd::dtd::<VerboseError<&str>>(source).unwrap();
+        let cst = Cst::new(source, nodes);
+
+        assert_eq!(cst.to_string(), source);
+        assert_eq!(
+            describe(&cst),
+            [
+                ("comment", "<!-- License -->"),
+                ("whitespace", "\r\n\r\n"),
+                (
+                    "include",
+                    "<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">"
+                ),
+                ("whitespace", "\n"),
+                ("ignored", "%brandDTD;"),
+                ("whitespace", "\n\n  "),
+                ("message", "<!ENTITY back.label   \"Back\">"),
+                ("whitespace", "\n"),
+                ("junk", "<!UNKNOWN foo>\n"),
+                ("message", "<!ENTITY forward.label \"Forward\">"),
+            ]
+        );
+        assert_eq!(cst.nodes().count(), 5);
+    }
+
+    #[test]
+    fn test_properties_round_trip() {
+        let source = "# License\n#   indented line\n\n\n  back.label = Back \\\n     continued  \r\n= junk\nforward.label:Forward\n";
+        let (_, nodes) = properties::properties::<VerboseError<&str>>(source).unwrap();
+        let cst = Cst::new(source, nodes);
+
+        assert_eq!(cst.to_string(), source);
+        assert_eq!(
+            describe(&cst),
+            [
+                ("comment", "# License\n#   indented line"),
+                ("whitespace", "\n\n\n  "),
+                ("message", "back.label = Back \\\n     continued  "),
+                ("whitespace", "\r\n"),
+                ("junk", "= junk\n"),
+                ("message", "forward.label:Forward"),
+                ("whitespace", "\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(Cst::new("", Vec::new()).elements, []);
+        assert_eq!(
+            Cst::new(" \n", Vec::new()).elements,
+            [Element::Whitespace(" \n")]
+        );
+    }
+}
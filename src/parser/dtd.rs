@@ -10,18 +10,15 @@ use nom::{
     sequence::*,
     *,
 };
-
-// fn blank<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
-//     i: &'a str,
-// ) -> IResult<&'a str, &'a str, E> {}
+use std::collections::{HashMap, HashSet};
 
 // <!-- LOCALIZATION NOTE (securityOverride.warningContent) Lorem ipsum -->
 // 0000
 fn comment_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Option<Node>, E> {
+) -> IResult<&'a str, Option<Node<'a>>, E> {
     map(
-        tuple((
+        consumed(tuple((
             tag("<!--"),            // 0
             opt(whitespace),        // 1
             opt(localization_note), // 2
@@ -29,12 +26,14 @@ fn comment_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
             take_until("-->"),      // 4
             opt(whitespace),        // 5
             tag("-->"),             // 6
-        )),
-        |tuple| {
+        ))),
+        |(comment, tuple)| {
             Some(
                 Comment {
-                    key: tuple.2,
+                    targets: tuple.2.clone().map(|note| note.1).unwrap_or_default(),
                     value: tuple.4.trim_end().into(),
+                    span: Span::new(i, comment),
+                    targets_span: tuple.2.map(|note| Span::new(i, note.0)),
                 }
                 .into(),
             )
@@ -42,122 +41,668 @@ fn comment_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     )(i)
 }
 
+/// Tidy the text of a comment that starts in the column. Comments in Firefox often have
+/// a gutter of dashes under the start of the comment, which isn't part of the text:
+///
+/// ```text
+/// <!-- LOCALIZATION NOTE (intro.label): The first paragraph.
+///    -
+///    - The second paragraph. -->
+/// ```
+fn tidy_comment(text: &str, column: usize) -> String {
+    let mut lines = text.lines();
+    let first = lines.next();
+    let lines: Vec<&str> = lines.collect();
+    // The dashes of the gutter are at most in the column of the dashes of `<!--`, which
+    // is the 0-based column of the `<` plus 3.
+    let gutter_end = column + 2;
+    let lines = lines
+        .iter()
+        .map(|line| without_gutter(line, gutter_end))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or(lines);
+    comment_value(first, &lines)
+}
+
+/// Remove the dash of a gutter from the start of a line, if it's in a column up to the
+/// `gutter_end`.
+fn without_gutter(line: &str, gutter_end: usize) -> Option<&str> {
+    let content = line.trim_start();
+    if content.is_empty() {
+        return Some(content);
+    }
+    let column = line[..line.len() - content.len()].chars().count();
+    let rest = content.strip_prefix('-').filter(|_| column <= gutter_end)?;
+    if rest.trim().is_empty() {
+        Some("")
+    } else {
+        rest.strip_prefix(' ')
+    }
+}
+
+/// A value in either double or single quotes. The other quote can be used freely
+/// inside of it, and a backslash escapes the next character. Line endings are
+/// normalized to `\n` like an XML parser does, but the rest of the whitespace is kept
+/// as it is, since it's part of the value.
 fn quoted_string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
 ) -> IResult<&'a str, String, E> {
+    context("quoted_string", alt((quoted('"'), quoted('\''))))(i)
+}
+
+fn quoted<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    quote: char,
+) -> impl FnMut(&'a str) -> IResult<&'a str, String, E> {
+    let special = if quote == '"' { "\\\"" } else { "\\'" };
     map(
         tuple((
-            char('\"'),
-            opt(escaped(none_of("\\\""), '\\', one_of("\\\""))),
-            char('\"'),
+            char(quote),                                           // 0
+            opt(escaped(none_of(special), '\\', one_of(special))), // 1
+            char(quote),                                           // 2
         )),
-        |tuple: (char, Option<&str>, char)| {
-            if let Some(string) = tuple.1 {
-                string.replace("\\\"", "\"").replace("\\\\", "\\")
-            } else {
-                String::from("")
+        |tuple: (char, Option<&str>, char)| unescape(tuple.1.unwrap_or("")),
+    )
+}
+
+/// Remove the backslash escapes, and normalize the line endings.
+fn unescape(string: &str) -> String {
+    let mut value = String::with_capacity(string.len());
+    let mut chars = string.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next()),
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                value.push('\n');
             }
-        },
-    )(i)
+            c => value.push(c),
+        }
+    }
+    value
 }
 
+/// The parts of an entity declaration after the `<!ENTITY`.
+enum Entity<'a> {
+    Message(&'a str, Pattern),
+    /// An external parameter entity, with its name and the URL of the DTD.
+    Include(&'a str, String),
+}
+
+/// ```text
 /// <!ENTITY ldb.visualDebugging.label "Visual Debugging">
 ///          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+/// ```
 fn message_attributes<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Option<Node>, E> {
+) -> IResult<&'a str, Entity<'a>, E> {
     map(
         tuple((message_key, whitespace, quoted_string, tag(">"))),
+        |tuple| Entity::Message(tuple.0, value_pattern(&tuple.2)),
+    )(i)
+}
+
+/// Decode a character reference like `&#x2026;` or `&#160;`, or one of the predefined
+/// XML entities like `&amp;`, from the name between the `&` and `;`.
+fn decode_reference(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => number.parse(),
+            };
+            code.ok().and_then(char::from_u32).filter(|&c| c != '\0')
+        }
+    }
+}
+
+/// Split a value into text, references to other entities like `&brandShortName;`, and
+/// tags of markup like `<html:a href="...">`. The character references and predefined
+/// entities are decoded into the text, as Fluent has no entities. An `&` or `<` that
+/// doesn't start a reference or a tag is kept as text.
+fn value_pattern(value: &str) -> Pattern {
+    let mut pattern = Pattern::default();
+    let mut rest = value;
+    while let Some(start) = rest.find(['&', '<']) {
+        pattern.push_text(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with('<') {
+            match alt((start_tag::<()>, end_tag))(rest) {
+                Ok((after, element)) => {
+                    pattern.push(element);
+                    rest = after;
+                }
+                Err(_) => {
+                    pattern.push_text("<");
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+        let name = rest.find(';').map(|end| &rest[1..end]);
+        match name {
+            Some(name) => {
+                match decode_reference(name) {
+                    Some(c) => pattern.push_text(c.encode_utf8(&mut [0; 4])),
+                    None if all_consuming(entity_name::<()>)(name).is_ok() => {
+                        pattern.push_reference(name)
+                    }
+                    None => {
+                        pattern.push_text("&");
+                        rest = &rest[1..];
+                        continue;
+                    }
+                }
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                pattern.push_text(rest);
+                rest = "";
+            }
+        }
+    }
+    pattern.push_text(rest);
+    pattern
+}
+
+/// ```text
+/// href="https://example.com"
+/// ```
+fn markup_attribute<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, (String, String), E> {
+    map(
+        tuple((
+            entity_name,     // 0
+            opt(whitespace), // 1
+            char('='),       // 2
+            opt(whitespace), // 3
+            alt((
+                delimited(char('"'), take_till(|c| c == '"'), char('"')),
+                delimited(char('\''), take_till(|c| c == '\''), char('\'')),
+            )), // 4
+        )),
+        |tuple| (tuple.0.into(), value_pattern(tuple.4).to_string()),
+    )(i)
+}
+
+/// ```text
+/// <html:a href="https://example.com">
+/// ```
+fn start_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, PatternElement, E> {
+    map(
+        tuple((
+            char('<'),                                     // 0
+            entity_name,                                   // 1
+            many0(preceded(whitespace, markup_attribute)), // 2
+            opt(whitespace),                               // 3
+            opt(char('/')),                                // 4
+            char('>'),                                     // 5
+        )),
         |tuple| {
-            Some(
+            PatternElement::StartTag(Tag {
+                name: tuple.1.into(),
+                attributes: tuple.2,
+                self_closing: tuple.4.is_some(),
+            })
+        },
+    )(i)
+}
+
+/// ```text
+/// </html:a>
+/// ```
+fn end_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, PatternElement, E> {
+    map(
+        tuple((tag("</"), entity_name, opt(whitespace), char('>'))),
+        |tuple| PatternElement::EndTag(tuple.1.into()),
+    )(i)
+}
+
+/// The name of an entity, like `brandDTD`.
+fn entity_name<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, &'a str, E> {
+    context(
+        "entity_name",
+        recognize(pair(
+            satisfy(|c| c.is_alphabetic() || c == '_' || c == ':'),
+            take_while(|c: char| c.is_alphanumeric() || "_:.-".contains(c)),
+        )),
+    )(i)
+}
+
+/// The location of an external entity, which is the URL of the file.
+///
+/// ```text
+/// SYSTEM "chrome://branding/locale/brand.dtd"
+/// PUBLIC "-//MOZILLA//DTD Brand//EN" "chrome://branding/locale/brand.dtd"
+/// ```
+fn external_id<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, String, E> {
+    context(
+        "external_id",
+        alt((
+            preceded(pair(tag("SYSTEM"), whitespace), quoted_string),
+            map(
+                tuple((
+                    tag("PUBLIC"), // 0
+                    whitespace,    // 1
+                    quoted_string, // 2
+                    whitespace,    // 3
+                    quoted_string, // 4
+                )),
+                |tuple| tuple.4,
+            ),
+        )),
+    )(i)
+}
+
+/// ```text
+/// <!ENTITY % brandDTD SYSTEM "chrome://branding/locale/brand.dtd" >
+///          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+///          12222222234444444444444444444444444444444444444444444456
+/// ```
+fn parameter_entity_attributes<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Entity<'a>, E> {
+    context(
+        "parameter_entity",
+        map(
+            tuple((
+                char('%'),       // 1
+                whitespace,      // 2
+                entity_name,     // 3 brandDTD
+                whitespace,      // 4
+                external_id,     // 5 SYSTEM "chrome://branding/locale/brand.dtd"
+                opt(whitespace), // 6
+                char('>'),       // 7
+            )),
+            |tuple| Entity::Include(tuple.2, tuple.4),
+        ),
+    )(i)
+}
+
+/// A parameter entity with its value in the declaration, which is returned along with
+/// its name.
+///
+/// ```text
+/// <!ENTITY % windows "INCLUDE">
+/// ```
+fn internal_parameter_entity<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, (&'a str, String), E> {
+    context(
+        "parameter_entity",
+        map(
+            tuple((
+                tag("<!ENTITY"), // 0
+                whitespace,      // 1
+                char('%'),       // 2
+                whitespace,      // 3
+                entity_name,     // 4 windows
+                whitespace,      // 5
+                quoted_string,   // 6 "INCLUDE"
+                opt(whitespace), // 7
+                char('>'),       // 8
+            )),
+            |tuple| (tuple.4, tuple.6),
+        ),
+    )(i)
+}
+
+/// A reference to a parameter entity, which includes it at this point of the file. The
+/// name of the entity is returned.
+///
+/// ```text
+/// %brandDTD;
+/// ```
+fn parameter_entity_reference<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, &'a str, E> {
+    delimited(char('%'), entity_name, char(';'))(i)
+}
+
+fn entity_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Option<Node<'a>>, E> {
+    map(
+        consumed(context(
+            "entity",
+            tuple((
+                tag("<!ENTITY"),
+                whitespace,
+                alt((message_attributes, parameter_entity_attributes)),
+            )),
+        )),
+        |(entity, tuple)| match tuple.2 {
+            Entity::Message(key, value) => Some(
                 Message {
-                    key: tuple.0,
-                    value: tuple.2.to_string(),
+                    key: key.into(),
+                    value,
+                    span: Span::new(i, entity),
+                    key_span: Span::new(i, key),
                 }
                 .into(),
-            )
+            ),
+            Entity::Include(name, url) => Some(
+                Include {
+                    name,
+                    url,
+                    referenced: false,
+                    span: Span::new(i, entity),
+                }
+                .into(),
+            ),
         },
     )(i)
 }
 
-fn ascii_alphanumeric<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+/// The XML declaration and other processing instructions, which are skipped.
+///
+/// ```text
+/// <?xml version="1.0" encoding="UTF-8"?>
+/// ```
+fn processing_instruction<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, &'a str, E> {
-    context(
-        "ascii_alphanumeric",
-        take_while(|c: char| c.is_ascii_alphanumeric()),
+) -> IResult<&'a str, Option<Node<'a>>, E> {
+    value(
+        None,
+        context(
+            "processing_instruction",
+            tuple((tag("<?"), take_until("?>"), tag("?>"))),
+        ),
     )(i)
 }
 
-// Discard the following information:
-//
-// <!ENTITY % brandDTD SYSTEM "chrome://branding/locale/brand.dtd" >
-//          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
-//          12333333334555555677777777777777777777777777777777777789A
-// %brandDTD;
-// ^^^^^^^^^^
-// BCCCCCCCCD
-fn entity_percent_attribute<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+/// The declarations that don't declare entities, which are skipped. Their quoted
+/// values can contain a `>`.
+///
+/// ```text
+/// <!ELEMENT window (menubar?, box*)>
+/// <!ATTLIST window title CDATA "Untitled>">
+/// ```
+fn markup_declaration<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Option<Node>, E> {
+) -> IResult<&'a str, Option<Node<'a>>, E> {
     value(
         None,
         context(
-            "entity_percent_attribute",
+            "markup_declaration",
             tuple((
-                char('%'),          // 1
-                whitespace,         // 2
-                ascii_alphanumeric, // 3 brandDTD
-                whitespace,         // 4,
-                ascii_alphanumeric, // 5 SYSTEM
-                whitespace,         // 6
-                quoted_string,      // 7 "chrome://branding/locale/brand.dtd"
-                opt(whitespace),    // 8
-                char('>'),          // 9
-                opt(whitespace),    // A
-                char('%'),          // B
-                ascii_alphanumeric, // C brandDTD
-                char(';'),          // D
+                alt((tag("<!ELEMENT"), tag("<!ATTLIST"), tag("<!NOTATION"))), // 0
+                many0(alt((recognize(quoted_string), is_not("\"'>")))),       // 1
+                char('>'),                                                    // 2
             )),
         ),
     )(i)
 }
 
-fn entity_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+/// A document type declaration is skipped, but the declarations in its internal
+/// subset are parsed like the rest of the file. Returns whether it has an internal
+/// subset, which is then open.
+///
+/// ```text
+/// <!DOCTYPE window SYSTEM "chrome://global/locale/window.dtd" [
+/// <!ENTITY back.label "Back">
+/// ]>
+/// ```
+fn doctype<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Option<Node>, E> {
+) -> IResult<&'a str, bool, E> {
     map(
         context(
-            "entity",
+            "doctype",
             tuple((
-                tag("<!ENTITY"),
-                whitespace,
-                alt((message_attributes, entity_percent_attribute)),
+                tag("<!DOCTYPE"),                       // 0
+                whitespace,                             // 1
+                entity_name,                            // 2
+                opt(preceded(whitespace, external_id)), // 3
+                opt(whitespace),                        // 4
+                alt((tag("["), tag(">"))),              // 5
             )),
         ),
-        |tuple| tuple.2,
+        |tuple| tuple.5 == "[",
     )(i)
 }
 
-pub fn dtd<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+/// The start of a conditional section, up to its contents. Returns the keyword, which
+/// is `INCLUDE`, `IGNORE` or a reference to a parameter entity.
+///
+/// ```text
+/// <![INCLUDE[ <!ENTITY back.label "Back"> ]]>
+/// <![IGNORE[ <!ENTITY old.label "Old"> ]]>
+/// <![%windows;[ <!ENTITY windows.label "Windows"> ]]>
+/// ```
+fn conditional_section_start<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Vec<Node>, E> {
+) -> IResult<&'a str, &'a str, E> {
     context(
-        "dtd",
-        fold_many0(
-            tuple((opt(whitespace), alt((comment_tag, entity_tag)))),
-            Vec::new(),
-            |mut entities: Vec<_>, tuple| {
-                if let Some(entity) = tuple.1 {
-                    // Kind of a bad clone.
-                    entities.push(entity);
-                }
-                entities
-            },
+        "conditional_section",
+        map(
+            tuple((
+                tag("<!["),      // 0
+                opt(whitespace), // 1
+                alt((
+                    tag("INCLUDE"),
+                    tag("IGNORE"),
+                    recognize(parameter_entity_reference),
+                )), // 2
+                opt(whitespace), // 3
+                char('['),       // 4
+            )),
+            |tuple| tuple.2,
         ),
     )(i)
 }
 
+/// The contents of an `IGNORE` section, up to and including its end. The sections can
+/// be nested.
+fn ignored_section_contents<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, &'a str, E> {
+    let mut depth = 1;
+    let mut rest = i;
+    while depth > 0 {
+        let start = rest.find("<![");
+        let end = rest.find("]]>");
+        match (start, end) {
+            (Some(start), Some(end)) if start < end => {
+                depth += 1;
+                rest = &rest[start + 3..];
+            }
+            (_, Some(end)) => {
+                depth -= 1;
+                rest = &rest[end + 3..];
+            }
+            (_, None) => {
+                return Err(nom::Err::Error(E::from_error_kind(
+                    rest,
+                    ErrorKind::TakeUntil,
+                )));
+            }
+        }
+    }
+    Ok((rest, &i[..i.offset(rest)]))
+}
+
+/// A section of a DTD that contains declarations.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Section {
+    /// An `INCLUDE` section, which ends with `]]>`.
+    Conditional,
+    /// The internal subset of a document type declaration, which ends with `]>`.
+    InternalSubset,
+}
+
+/// The end of a conditional section, or of the internal subset of a document type
+/// declaration.
+fn section_end<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Section, E> {
+    context(
+        "section_end",
+        alt((
+            value(Section::Conditional, tag("]]>")),
+            value(
+                Section::InternalSubset,
+                tuple((char(']'), opt(whitespace), char('>'))),
+            ),
+        )),
+    )(i)
+}
+
+fn entry<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Option<Node<'a>>, E> {
+    alt((
+        processing_instruction,
+        markup_declaration,
+        comment_tag,
+        entity_tag,
+    ))(i)
+}
+
+/// What is known about a DTD as its entries are parsed in order. The parameter entities
+/// and the sections change how the entries that follow them are parsed.
+#[derive(Default)]
+struct Declarations<'a> {
+    /// The parameter entities that are referenced, which are only ever entries of their
+    /// own, and not part of a comment or a value.
+    references: HashSet<&'a str>,
+    /// The values of the parameter entities that are declared in the file.
+    internal_entities: HashMap<&'a str, String>,
+    /// The sections that are open, with the innermost one last.
+    sections: Vec<Section>,
+}
+
+impl<'a> Declarations<'a> {
+    /// Parse the next entry of the DTD, where `source` is the whole file.
+    fn entry(
+        &mut self,
+        source: &'a str,
+        i: &'a str,
+    ) -> IResult<&'a str, Option<Node<'a>>, VerboseError<&'a str>> {
+        if let Ok((rest, name)) = parameter_entity_reference::<()>(i) {
+            self.references.insert(name);
+            return Ok((rest, self.expand(source, &i[..i.offset(rest)], name)));
+        }
+        if let Ok((rest, (name, value))) = internal_parameter_entity::<()>(i) {
+            // The first declaration of an entity is the one that counts.
+            self.internal_entities.entry(name).or_insert(value);
+            return Ok((rest, None));
+        }
+        if let Ok((rest, keyword)) = conditional_section_start::<()>(i) {
+            return match self.keyword(keyword) {
+                "IGNORE" => map(ignored_section_contents, |_| None)(rest),
+                _ => {
+                    self.sections.push(Section::Conditional);
+                    Ok((rest, None))
+                }
+            };
+        }
+        if let Ok((rest, has_internal_subset)) = doctype::<()>(i) {
+            if has_internal_subset {
+                self.sections.push(Section::InternalSubset);
+            }
+            return Ok((rest, None));
+        }
+        if let Ok((rest, section)) = section_end::<()>(i) {
+            // Only the innermost open section can end here.
+            if self.sections.last() == Some(&section) {
+                self.sections.pop();
+                return Ok((rest, None));
+            }
+            let end = &i[..i.offset(rest)];
+            let message = format!("The {} doesn't end a section", end);
+            return Ok((rest, Some(junk(source, end, message))));
+        }
+        entry(i)
+    }
+
+    /// The keyword of a conditional section, with a reference to a parameter entity
+    /// replaced by its value. An entity that isn't declared in the file can't be
+    /// evaluated, so its section is treated as included.
+    fn keyword<'k>(&'k self, keyword: &'k str) -> &'k str {
+        match keyword.strip_prefix('%').and_then(|k| k.strip_suffix(';')) {
+            Some(name) => self
+                .internal_entities
+                .get(name)
+                .map_or("INCLUDE", |value| value.trim()),
+            None => keyword,
+        }
+    }
+
+    /// A reference to a parameter entity that is declared in the file would include
+    /// the declarations of its value here. These can't be migrated, so the reference is
+    /// kept as Junk, unless the value is empty.
+    fn expand(&self, source: &'a str, reference: &'a str, name: &str) -> Option<Node<'a>> {
+        match self.internal_entities.get(name) {
+            Some(value) if !value.trim().is_empty() => {
+                let message = format!(
+                    "The declarations in the value of the parameter entity {} weren't migrated",
+                    reference
+                );
+                Some(junk(source, reference, message))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Keep an entry that was parsed, but can't be migrated, as Junk. The entry is at the
+/// start of the input of the parser, and `source` is the whole file.
+fn junk<'a>(source: &'a str, entry: &'a str, message: String) -> Node<'a> {
+    Node::Junk(Junk {
+        content: entry,
+        span: Span::new(entry, entry),
+        diagnostic: Diagnostic::new(source, source.offset(entry), message),
+    })
+}
+
+/// The next entry could start at a comment, a declaration, a processing instruction,
+/// or the end of a section.
+fn entry_boundary(i: &str) -> usize {
+    ["<!", "<?", "]]>", "]>"]
+        .iter()
+        .filter_map(|tag| i.find(tag))
+        .min()
+        .unwrap_or(i.len())
+}
+
+/// Parse all of the entries in a DTD file. Anything that isn't understood is skipped
+/// up to the next entity or comment and kept as a Junk node, so this never fails.
+pub fn dtd<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Vec<Node<'a>>, E> {
+    let mut declarations = Declarations::default();
+    let mut nodes = entries(i, |rest| declarations.entry(i, rest), entry_boundary);
+    for node in &mut nodes {
+        match node {
+            // A parameter entity is only included where it's referenced.
+            Node::Include(include) => {
+                include.referenced = declarations.references.contains(include.name);
+            }
+            // The column of the comment is only known once it's positioned in the file.
+            Node::Comment(comment) => {
+                comment.value = tidy_comment(&comment.value, comment.span.start.column);
+            }
+            _ => {}
+        }
+    }
+    Ok(("", nodes))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -194,7 +739,7 @@ mod test {
                 Comment {
                     input: comment.input,
                     comment: &parsed_comment.value,
-                    key: parsed_comment.key,
+                    targets: parsed_comment.targets,
                     after: result.0,
                 }
             );
@@ -205,56 +750,66 @@ mod test {
         struct Comment<'s> {
             input: &'s str,
             comment: &'s str,
-            key: Option<&'s str>,
+            targets: Vec<NoteTarget<'s>>,
             after: &'s str,
         }
 
         assert(Comment {
             input: "<!-- This is a comment -->",
             comment: "This is a comment",
-            key: None,
+            targets: vec![],
             after: "",
         });
         assert(Comment {
             input: "<!-- This is a comment --> after",
             comment: "This is a comment",
-            key: None,
+            targets: vec![],
             after: " after",
         });
         assert(Comment {
             input: "<!-- --> -->",
             comment: "",
-            key: None,
+            targets: vec![],
             after: " -->",
         });
         assert(Comment {
             input: "<!-- --> after",
             comment: "",
-            key: None,
+            targets: vec![],
             after: " after",
         });
         assert(Comment {
             input: "<!---->",
             comment: "",
-            key: None,
+            targets: vec![],
             after: "",
         });
         assert(Comment {
             input: "<!----> after ",
             comment: "",
-            key: None,
+            targets: vec![],
             after: " after ",
         });
         assert(Comment {
             input: "<!----> <!---->",
             comment: "",
-            key: None,
+            targets: vec![],
             after: " <!---->",
         });
         assert(Comment {
             input: "<!-- LOCALIZATION NOTE (key.value) Comment -->",
             comment: "Comment",
-            key: Some("key.value"),
+            targets: vec![NoteTarget::Key("key.value")],
+            after: "",
+        });
+        assert(Comment {
+            input: "<!-- LOCALIZATION NOTE (back.label, back.accesskey, menu.*): Comment -->",
+            comment: "Comment",
+            targets: vec![
+                NoteTarget::Key("back.label"),
+                NoteTarget::Key("back.accesskey"),
+                NoteTarget::Wildcard("menu.*"),
+            ],
             after: "",
         });
 
@@ -278,6 +833,68 @@ mod test {
         assert("\"test \\\\ escaped\"", "test \\ escaped");
         assert("\"test \\\" escaped \\\" twice\"", "test \" escaped \" twice");
         assert_err("\"text with a newline\n");
+
+        assert("'test'", "test");
+        assert("''", "");
+        assert("'It\"s'", "It\"s");
+        assert("\"It's\"", "It's");
+        assert("'test \\' escaped'", "test ' escaped");
+        assert_err("'mismatched\"");
+        assert_err("\"mismatched'");
+
+        assert("\"first\n  second\"", "first\n  second");
+        assert("'first\r\n  second\rthird'", "first\n  second\nthird");
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_value_pattern() {
+        let assert = |value, decoded: &str| assert_eq!(value_pattern(value), decoded.into());
+        let reference = |name: &str| PatternElement::EntityReference(name.into());
+        let text = |text: &str| PatternElement::Text(text.into());
+
+        assert("No references", "No references");
+        assert("Wait&#x2026;", "Wait…");
+        assert("Wait&#X2026;", "Wait…");
+        assert("10&#160;MB", "10\u{A0}MB");
+        assert("&lt;b&gt; &amp; &quot;&apos;", "<b> & \"'");
+        assert("&amp;lt;", "&lt;");
+        assert("&#128512;", "😀");
+        assert("& &#; &#xZZ; &#0; &#x110000;", "& &#; &#xZZ; &#0; &#x110000;");
+        assert("Fish & chips; &unterminated", "Fish & chips; &unterminated");
+
+        assert_eq!(
+            value_pattern("&brandShortName; & &vendor.name;&#x2026;").elements,
+            [
+                reference("brandShortName"),
+                text(" & "),
+                reference("vendor.name"),
+                text("…"),
+            ]
+        );
+    
+        assert("1 < 2 <3> </ >", "1 < 2 <3> </ >");
+        assert("&lt;b&gt;", "<b>");
+        assert_eq!(
+            value_pattern(
+                "<html:a href='https://example.com/?a=1&amp;b=2'>&brandShortName;</html:a><br/>"
+            )
+            .elements,
+            [
+                PatternElement::StartTag(Tag {
+                    name: "html:a".into(),
+                    attributes: vec![("href".into(), "https://example.com/?a=1&b=2".into())],
+                    self_closing: false,
+                }),
+                reference("brandShortName"),
+                PatternElement::EndTag("html:a".into()),
+                PatternElement::StartTag(Tag {
+                    name: "br".into(),
+                    self_closing: true,
+                    ..Default::default()
+                }),
+            ]
+        );
     }
 
     #[test]
@@ -288,10 +905,12 @@ mod test {
                 "<!ENTITY ldb.MainWindow.title \"Layout Debugger\">"
             )
             .1
-            .unwrap(),
+            .unwrap()
+            .without_spans(),
             Message {
                 key: "ldb.MainWindow.title".into(),
                 value: "Layout Debugger".into(),
+                ..Default::default()
             }
             .into()
         );
@@ -301,10 +920,43 @@ mod test {
                 "<!ENTITY performanceUI.toolbar.js-calltree \"Call Tree\">"
             )
             .1
-            .unwrap(),
+            .unwrap()
+            .without_spans(),
             Message {
                 key: "performanceUI.toolbar.js-calltree".into(),
                 value: "Call Tree".into(),
+                ..Default::default()
+            }
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_single_quoted_entities() {
+        assert_eq!(
+            parse!(entity_tag, "<!ENTITY quote.label 'Say \"Hi\"'>")
+                .1
+                .unwrap()
+                .without_spans(),
+            Message {
+                key: "quote.label".into(),
+                value: "Say \"Hi\"".into(),
+                ..Default::default()
+            }
+            .into()
+        );
+        assert_eq!(
+            parse!(
+                entity_tag,
+                "<!ENTITY multiline.label \"First line\n    second line\">"
+            )
+            .1
+            .unwrap()
+            .without_spans(),
+            Message {
+                key: "multiline.label".into(),
+                value: "First line\n    second line".into(),
+                ..Default::default()
             }
             .into()
         );
@@ -314,7 +966,94 @@ mod test {
     fn test_entity_percent() {
         let text =
             "<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\" >\n%brandDTD;";
-        assert_eq!(parse!(entity_tag, text).1, None);
+        assert_eq!(
+            parse!(entity_tag, text).1.unwrap().without_spans(),
+            Include {
+                name: "brandDTD",
+                url: "chrome://branding/locale/brand.dtd".into(),
+                ..Default::default()
+            }
+            .into()
+        );
+        assert_eq!(
+            parse!(parameter_entity_reference, "%brandDTD;").1,
+            "brandDTD"
+        );
+
+        let text = "<!ENTITY % global.dtd PUBLIC \"-//MOZILLA//DTD Global//EN\" 'global.dtd'>";
+        assert_eq!(
+            parse!(entity_tag, text).1.unwrap().without_spans(),
+            Include {
+                name: "global.dtd",
+                url: "global.dtd".into(),
+                ..Default::default()
+            }
+            .into()
+        );
+        assert_eq!(
+            parse!(
+                internal_parameter_entity,
+                "<!ENTITY % internal \"INCLUDE\">"
+            )
+            .1,
+            ("internal", "INCLUDE".into())
+        );
+    }
+
+    #[test]
+    fn test_includes() {
+        let (_, nodes) = parse!(
+            dtd,
+            "<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">
+%brandDTD;
+<!ENTITY % unused SYSTEM \"unused.dtd\">
+<!ENTITY about \"About &brandShortName;\">"
+        );
+        assert_eq!(
+            without_spans(nodes),
+            [
+                Node::Include(Include {
+                    name: "brandDTD",
+                    url: "chrome://branding/locale/brand.dtd".into(),
+                    referenced: true,
+                    ..Default::default()
+                }),
+                Node::Include(Include {
+                    name: "unused",
+                    url: "unused.dtd".into(),
+                    referenced: false,
+                    ..Default::default()
+                }),
+                Node::Message(Message {
+                    key: "about".into(),
+                    value: Pattern {
+                        elements: vec![
+                            PatternElement::Text("About ".into()),
+                            PatternElement::EntityReference("brandShortName".into()),
+                        ]
+                    },
+                    ..Default::default()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_includes_referenced_in_comments() {
+        let (_, nodes) = parse!(
+            dtd,
+            "<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">
+<!-- %brandDTD; -->
+<!ENTITY about \"About %brandDTD;\">"
+        );
+        let referenced: Vec<bool> = nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Include(include) => Some(include.referenced),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(referenced, [false]);
     }
 
     #[test]
@@ -333,37 +1072,206 @@ mod test {
             "
         );
         assert_eq!(
-            entities,
+            without_spans(entities),
             [
                 Node::Comment(Comment {
                     value: "preamble".into(),
-                    key: None
+                    targets: vec![],
+                    ..Default::default()
                 }),
                 Node::Message(Message {
                     key: "ldb.MainWindow.title".into(),
                     value: "Layout Debugger".into(),
+                    ..Default::default()
                 }),
                 Node::Message(Message {
                     key: "ldb.BackButton.label".into(),
                     value: "Back".into(),
+                    ..Default::default()
                 }),
                 Node::Message(Message {
                     key: "ldb.ForwardButton.label".into(),
                     value: "Forward".into(),
+                    ..Default::default()
                 }),
                 Node::Message(Message {
                     key: "ldb.ReloadButton.label".into(),
                     value: "Reload".into(),
+                    ..Default::default()
                 }),
                 Node::Message(Message {
                     key: "ldb.StopButton.label".into(),
                     value: "Stop".into(),
+                    ..Default::default()
                 }),
                 Node::Message(Message {
                     key: "ldb.StopButton.label2".into(),
                     value: "Stop Again".into(),
+                    ..Default::default()
                 }),
             ]
         );
     }
+
+    #[test]
+    fn test_multiline_comments() {
+        let (_, nodes) = parse!(
+            dtd,
+            "<!-- This Source Code Form is subject to the terms of the Mozilla Public
+   - License, v. 2.0. If a copy of the MPL was not distributed with this
+   - file, You can obtain one at http://mozilla.org/MPL/2.0/. -->
+
+<!-- LOCALIZATION NOTE (first): The first paragraph,
+   - which wraps.
+   -
+   -   - A list item.
+   -   - Another item.
+   -->
+  <!-- LOCALIZATION NOTE (second): Indented comment
+     - with a gutter. -->
+<!-- LOCALIZATION NOTE (third): This can be:
+       - a file
+       - a folder -->
+"
+        );
+        let values: Vec<&str> = nodes
+            .iter()
+            .map(|node| match node {
+                Node::Comment(comment) => comment.value.as_str(),
+                node => panic!("Unexpected node {:?}", node),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "This Source Code Form is subject to the terms of the Mozilla Public\nLicense, v. 2.0. If a copy of the MPL was not distributed with this\nfile, You can obtain one at http://mozilla.org/MPL/2.0/.",
+                "The first paragraph,\nwhich wraps.\n\n  - A list item.\n  - Another item.",
+                "Indented comment\nwith a gutter.",
+                // The dashes are too far in to be a gutter.
+                "This can be:\n- a file\n- a folder",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skipped_declarations() {
+        let (_, nodes) = parse!(
+            dtd,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE window SYSTEM \"chrome://global/locale/window.dtd\" [
+  <!ENTITY % platformDTD SYSTEM \"chrome://global/locale/platform.dtd\">
+  %platformDTD;
+  <!ELEMENT window (menubar?, box*)>
+  <!ATTLIST window title CDATA \"Untitled>\" id ID #IMPLIED>
+  <!NOTATION gif SYSTEM \"image/gif\">
+  <!ENTITY first \"First\">
+]>
+<![INCLUDE[
+  <!ENTITY second \"Second\">
+]]>
+<![ IGNORE [
+  <!ENTITY ignored \"Ignored\">
+  <![INCLUDE[ <!ENTITY nested \"Nested\"> ]]>
+]]>
+<![%windows;[
+  <!ENTITY third \"Third\">
+]]>
+<!DOCTYPE empty>
+<!ENTITY fourth \"Fourth\">
+"
+        );
+        let keys: Vec<String> = nodes
+            .iter()
+            .map(|node| match node {
+                Node::Message(message) => message.key.to_string(),
+                Node::Include(include) => include.url.clone(),
+                node => panic!("Unexpected node {:?}", node),
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "chrome://global/locale/platform.dtd",
+                "first",
+                "second",
+                "third",
+                "fourth"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_entities() {
+        let (_, nodes) = parse!(
+            dtd,
+            "<!ENTITY % mac \"IGNORE\">
+<!ENTITY % win 'INCLUDE'>
+<!ENTITY % empty \"\">
+<!ENTITY % declarations \"<!ENTITY hidden 'Hidden'>\">
+<![%mac;[ <!ENTITY mac.label \"Mac\"> ]]>
+<![%win;[ <!ENTITY win.label \"Win\"> ]]>
+%empty;
+%declarations;
+]]>
+<!ENTITY last \"Last\">
+"
+        );
+        let nodes: Vec<String> = nodes
+            .iter()
+            .map(|node| match node {
+                Node::Message(message) => message.key.to_string(),
+                Node::Junk(junk) => format!("{}: {}", junk.content.trim(), junk.diagnostic),
+                node => panic!("Unexpected node {:?}", node),
+            })
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                "win.label",
+                "%declarations;: 8:1: The declarations in the value of the parameter entity %declarations; weren't migrated",
+                "]]>: 9:1: The ]]> doesn't end a section",
+                "last",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let source = "<!-- LOCALIZATION NOTE (first): Note -->
+<!ENTITY first \"First\">
+  <!ENTITY second
+    \"Second\">";
+        // The text of the span, then its start and end line and column.
+        let span = |span: Span| {
+            (
+                &source[span.range()],
+                (span.start.line, span.start.column),
+                (span.end.line, span.end.column),
+            )
+        };
+        let (_, nodes) = parse!(dtd, source);
+        match &nodes[..] {
+            [Node::Comment(comment), Node::Message(first), Node::Message(second)] => {
+                assert_eq!(
+                    span(comment.span),
+                    ("<!-- LOCALIZATION NOTE (first): Note -->", (1, 1), (1, 41))
+                );
+                assert_eq!(
+                    span(comment.targets_span.unwrap()),
+                    ("first", (1, 25), (1, 30))
+                );
+                assert_eq!(
+                    span(first.span),
+                    ("<!ENTITY first \"First\">", (2, 1), (2, 24))
+                );
+                assert_eq!(span(first.key_span), ("first", (2, 10), (2, 15)));
+                assert_eq!(
+                    span(second.span),
+                    ("<!ENTITY second\n    \"Second\">", (3, 3), (4, 14))
+                );
+                assert_eq!(span(second.key_span), ("second", (3, 12), (3, 18)));
+            }
+            _ => panic!("Unexpected nodes {:#?}", nodes),
+        }
+    }
 }
@@ -1,6 +1,10 @@
+pub mod cst;
 pub mod dtd;
 pub mod properties;
 
+use crate::error::Error;
+use std::borrow::Cow;
+
 use nom::{
     //
     branch::*,
@@ -16,7 +20,7 @@ use nom::{
 fn single_line_whitespace<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
 ) -> IResult<&'a str, &'a str, E> {
-    context("whitespace", take_while(|c| " \t".contains(c)))(i)
+    context("whitespace", take_while(|c| " \t\x0C".contains(c)))(i)
 }
 
 fn rest_of_line<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
@@ -27,7 +31,7 @@ fn rest_of_line<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
             alt((take_until("\n"), rest)), // 0
             opt(char('\n')),               // 1
         )),
-        |tuple| tuple.0,
+        |tuple: (&'a str, _)| tuple.0.strip_suffix('\r').unwrap_or(tuple.0),
     )(i)
 }
 
@@ -39,49 +43,405 @@ fn whitespace<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
 
 fn message_key<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, &str, E> {
-    take_while(|c: char| c.is_ascii_alphanumeric() || '.' == c || '-' == c)(i)
+) -> IResult<&'a str, &'a str, E> {
+    take_while(|c: char| c.is_alphanumeric() || "._-:".contains(c))(i)
 }
 
+/// A key in a LOCALIZATION NOTE, which can contain `*` wildcards.
+fn note_target<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, NoteTarget<'a>, E> {
+    map(
+        take_while1(|c: char| c.is_alphanumeric() || "._-:*".contains(c)),
+        |target: &str| {
+            if target.contains('*') {
+                NoteTarget::Wildcard(target)
+            } else {
+                NoteTarget::Key(target)
+            }
+        },
+    )(i)
+}
+
+/// The note can be about several keys, like `(foo.label, foo.accesskey)` or
+/// `(downloads.*)`. The source of the list of keys is returned along with them.
 fn localization_note<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, &'a str, E> {
+) -> IResult<&'a str, (&'a str, Vec<NoteTarget<'a>>), E> {
     map(
         // Capture localization notes.
         tuple((
-            tag("LOCALIZATION NOTE"),                   // 0
-            opt(whitespace),                            // 1
-            delimited(tag("("), message_key, tag(")")), // 2
-            opt(whitespace),                            // 3
-            opt(char(':')),                             // 4
+            tag("LOCALIZATION NOTE"), // 0
+            opt(whitespace),          // 1
+            delimited(
+                tag("("),
+                consumed(separated_list1(
+                    tuple((opt(whitespace), char(','), opt(whitespace))),
+                    note_target,
+                )),
+                tag(")"),
+            ), // 2
+            opt(whitespace),          // 3
+            opt(char(':')),           // 4
         )),
         |tuple| tuple.2,
     )(i)
 }
 
-// TODO - De-duplicate.
-macro_rules! parse {
-    ($fn:ident, $text:expr) => {{
-        let data: &str = $text;
-        match $fn::<nom::error::VerboseError<&str>>(data) {
-            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
-                panic!(
-                    "\n\nParsing Error:\n\n{}",
-                    nom::error::convert_error(data, e)
-                );
+/// Tidy the lines of a comment, keeping its paragraphs and the relative indentation of
+/// its lines, like in a list. The `first` line is the text that follows the start of a
+/// DTD comment or a LOCALIZATION NOTE, and doesn't count towards the indentation of the
+/// rest of the lines. Blank lines at the start and end are removed, and runs of blank
+/// lines become a single one.
+fn comment_value(first: Option<&str>, lines: &[&str]) -> String {
+    let indentation = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines = first
+        .map(str::trim)
+        .into_iter()
+        .chain(lines.iter().map(|line| {
+            // The indentation is all whitespace, which is ASCII.
+            line.get(indentation..).unwrap_or("").trim_end()
+        }));
+
+    let mut value = String::new();
+    let mut blank_lines = false;
+    for line in lines {
+        if line.is_empty() {
+            blank_lines = !value.is_empty();
+            continue;
+        }
+        if blank_lines {
+            value.push_str("\n\n");
+            blank_lines = false;
+        } else if !value.is_empty() {
+            value.push('\n');
+        }
+        value.push_str(line);
+    }
+    value
+}
+
+/// A location in the source text.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position {
+    /// The byte offset into the source.
+    pub offset: usize,
+    /// The 1-based line number.
+    pub line: usize,
+    /// The 1-based column, counted in characters.
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// Compute the position of a byte offset into the source.
+    pub fn new(source: &str, offset: usize) -> Self {
+        Position::default().advance(&source[..offset])
+    }
+
+    /// Move the position past some text.
+    fn advance(self, text: &str) -> Self {
+        match text.rfind('\n') {
+            Some(index) => Position {
+                offset: self.offset + text.len(),
+                line: self.line + text.matches('\n').count(),
+                column: text[index + 1..].chars().count() + 1,
+            },
+            None => Position {
+                offset: self.offset + text.len(),
+                line: self.line,
+                column: self.column + text.chars().count(),
+            },
+        }
+    }
+
+    /// Positions are computed relative to the input of the parser that found them.
+    /// Rebase one onto the position of that input in the whole source.
+    fn rebase(self, base: Position) -> Self {
+        Position {
+            offset: base.offset + self.offset,
+            line: base.line + self.line - 1,
+            column: if self.line == 1 {
+                base.column + self.column - 1
+            } else {
+                self.column
+            },
+        }
+    }
+}
+
+/// A range of the source text, from the start up to, but not including, the end.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// Find the span of `slice`, which must be a slice of `source`.
+    pub fn new(source: &str, slice: &str) -> Self {
+        let start = Position::new(source, source.offset(slice));
+        Span {
+            start,
+            end: start.advance(slice),
+        }
+    }
+
+    /// The byte range of the span, for indexing into the source.
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start.offset..self.end.offset
+    }
+
+    fn rebase(self, base: Position) -> Self {
+        Span {
+            start: self.start.rebase(base),
+            end: self.end.rebase(base),
+        }
+    }
+}
+
+/// A problem found while parsing a file, located at a line and column.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Diagnostic {
+    pub message: String,
+    pub position: Position,
+}
+
+impl Diagnostic {
+    pub fn new(source: &str, offset: usize, message: String) -> Self {
+        Diagnostic {
+            message,
+            position: Position::new(source, offset),
+        }
+    }
+
+    /// Report the input that a parser stopped at without consuming.
+    fn unparsed(source: &str, rest: &str) -> Self {
+        let line = rest.lines().next().unwrap_or("");
+        let excerpt: String = line.chars().take(60).collect();
+        let ellipsis = if excerpt.len() < line.len() {
+            "…"
+        } else {
+            ""
+        };
+        Diagnostic::new(
+            source,
+            source.offset(rest),
+            format!(
+                "Parsing stopped here, the rest of the file was not migrated: {}{}",
+                excerpt, ellipsis
+            ),
+        )
+    }
+
+    fn from_verbose_error(source: &str, error: VerboseError<&str>) -> Self {
+        let (input, _) = error.errors[0];
+        let reason = error
+            .errors
+            .iter()
+            .find_map(|(_, kind)| match kind {
+                VerboseErrorKind::Context(context) => Some(format!("Unable to parse {}", context)),
+                _ => None,
+            })
+            .unwrap_or_else(|| match error.errors[0].1 {
+                VerboseErrorKind::Char(c) => format!("Expected '{}'", c),
+                VerboseErrorKind::Nom(kind) => format!("Unable to parse ({:?})", kind),
+                VerboseErrorKind::Context(context) => format!("Unable to parse {}", context),
+            });
+        Diagnostic::new(source, source.offset(input), reason)
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.position.line, self.position.column, self.message
+        )
+    }
+}
+
+/// Parse every entry of a file, recovering from errors rather than stopping. When an
+/// entry can't be parsed, the input is skipped up to the next plausible entry boundary,
+/// as found by `boundary`, and the skipped region is recorded as a Junk node with a
+/// diagnostic. Positions in the diagnostics are relative to `source`.
+fn entries<'a, F, B>(source: &'a str, mut entry: F, boundary: B) -> Vec<Node<'a>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, Option<Node<'a>>, VerboseError<&'a str>>,
+    B: Fn(&'a str) -> usize,
+{
+    let mut nodes = Vec::new();
+    let mut rest = source;
+    // Track the position as the input is consumed, so that the spans of each entry can
+    // be rebased onto it.
+    let mut position = Position::default();
+    loop {
+        let trimmed = rest.trim_start_matches(|c| " \t\r\n".contains(c));
+        position = position.advance(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+        if rest.is_empty() {
+            return nodes;
+        }
+        let error = match entry(rest) {
+            Ok((next, _)) if next.len() == rest.len() => {
+                VerboseError::from_error_kind(rest, ErrorKind::Many0)
+            }
+            Ok((next, node)) => {
+                if let Some(mut node) = node {
+                    node.rebase(position);
+                    nodes.push(node);
+                }
+                position = position.advance(&rest[..rest.len() - next.len()]);
+                rest = next;
+                continue;
             }
-            Err(nom::Err::Incomplete(needed)) => {
-                panic!("Incomplete, needed: {:?}", needed);
+            Err(nom::Err::Error(error)) | Err(nom::Err::Failure(error)) => error,
+            Err(nom::Err::Incomplete(_)) => VerboseError::from_error_kind(rest, ErrorKind::Eof),
+        };
+        // Always make progress, even if the boundary is at the start of the input.
+        let first_char = rest.chars().next().map_or(0, char::len_utf8);
+        let end = first_char + boundary(&rest[first_char..]);
+        let content = &rest[..end];
+        let end_position = position.advance(content);
+        nodes.push(Node::Junk(Junk {
+            content,
+            span: Span {
+                start: position,
+                end: end_position,
+            },
+            diagnostic: Diagnostic::from_verbose_error(source, error),
+        }));
+        position = end_position;
+        rest = &rest[end..];
+    }
+}
+
+/// Run a top-level parser over an entire file, and collect every diagnostic for the
+/// parts of it that couldn't be migrated. These are the Junk nodes the parser recovered
+/// from, as well as any input that it stopped at without consuming, so that nothing is
+/// silently dropped. The nodes that were parsed are still returned.
+pub fn parse_complete<'a, F>(mut parser: F, source: &'a str) -> (Vec<Node<'a>>, Vec<Diagnostic>)
+where
+    F: FnMut(&'a str) -> IResult<&'a str, Vec<Node<'a>>, VerboseError<&'a str>>,
+{
+    match parser(source) {
+        Ok((rest, nodes)) => {
+            let mut diagnostics: Vec<Diagnostic> = nodes
+                .iter()
+                .filter_map(|node| match node {
+                    Node::Junk(junk) => Some(junk.diagnostic.clone()),
+                    _ => None,
+                })
+                .collect();
+            let rest = rest.trim_start();
+            if !rest.is_empty() {
+                diagnostics.push(Diagnostic::unparsed(source, rest));
             }
-            Ok(value) => value,
+            (nodes, diagnostics)
         }
-    }};
+        Err(nom::Err::Error(error)) | Err(nom::Err::Failure(error)) => (
+            Vec::new(),
+            vec![Diagnostic::from_verbose_error(source, error)],
+        ),
+        Err(nom::Err::Incomplete(_)) => (
+            Vec::new(),
+            vec![Diagnostic::new(
+                source,
+                source.len(),
+                "Unexpected end of file".into(),
+            )],
+        ),
+    }
+}
+
+/// Parse an entire file, failing with every diagnostic if any part of it couldn't be
+/// parsed. Use `parse_complete` to get at the nodes that could be parsed anyway.
+pub fn parse<'a, F>(parser: F, source: &'a str) -> Result<Vec<Node<'a>>, Error>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, Vec<Node<'a>>, VerboseError<&'a str>>,
+{
+    let (nodes, diagnostics) = parse_complete(parser, source);
+    if diagnostics.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(Error::Parse {
+            path: None,
+            diagnostics,
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Node<'a> {
     Message(Message<'a>),
     Comment(Comment<'a>),
+    Junk(Junk<'a>),
+    Include(Include<'a>),
+}
+
+impl<'a> Node<'a> {
+    /// The span of the entire node in the source.
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Message(message) => message.span,
+            Node::Comment(comment) => comment.span,
+            Node::Junk(junk) => junk.span,
+            Node::Include(include) => include.span,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn without_spans(mut self) -> Self {
+        match &mut self {
+            Node::Message(message) => {
+                message.span = Span::default();
+                message.key_span = Span::default();
+            }
+            Node::Comment(comment) => {
+                comment.span = Span::default();
+                comment.targets_span = None;
+            }
+            Node::Junk(junk) => junk.span = Span::default(),
+            Node::Include(include) => include.span = Span::default(),
+        }
+        self
+    }
+
+    fn rebase(&mut self, base: Position) {
+        match self {
+            Node::Message(message) => {
+                message.span = message.span.rebase(base);
+                message.key_span = message.key_span.rebase(base);
+            }
+            Node::Comment(comment) => {
+                comment.span = comment.span.rebase(base);
+                comment.targets_span = comment.targets_span.map(|span| span.rebase(base));
+            }
+            Node::Junk(junk) => junk.span = junk.span.rebase(base),
+            Node::Include(include) => include.span = include.span.rebase(base),
+        }
+    }
+}
+
+/// Reset the spans of the nodes, so that tests can compare the parsed values alone.
+#[cfg(test)]
+pub fn without_spans(nodes: Vec<Node>) -> Vec<Node> {
+    nodes.into_iter().map(Node::without_spans).collect()
 }
 
 impl<'a> From<Message<'a>> for Node<'a> {
@@ -90,10 +450,153 @@ impl<'a> From<Message<'a>> for Node<'a> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Message<'a> {
-    pub key: &'a str,
-    pub value: String,
+    pub key: Cow<'a, str>,
+    pub value: Pattern,
+    pub span: Span,
+    pub key_span: Span,
+}
+
+/// The value of a message, as text with references to other entities in it.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Pattern {
+    pub elements: Vec<PatternElement>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatternElement {
+    Text(String),
+    /// A reference to another entity, like `&brandShortName;` in a DTD.
+    EntityReference(String),
+    /// The start of an element of markup, like `<html:a href="...">`.
+    StartTag(Tag),
+    /// The end of an element of markup, with the name of the element.
+    EndTag(String),
+    /// A printf-style placeholder in a .properties value, like `%S` or `%1$S`.
+    Placeholder(Placeholder),
+}
+
+/// A placeholder, where a variable is inserted into the text, like printf's `%S`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Placeholder {
+    /// The 1-based position of the argument. Placeholders without an explicit position,
+    /// like `%S`, take the next one.
+    pub position: usize,
+    /// The source of the placeholder, like `%1$S`.
+    pub source: String,
+}
+
+/// The start tag of an element of markup in a value.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Tag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    /// The tag closes itself, like `<br/>`.
+    pub self_closing: bool,
+}
+
+impl Tag {
+    /// The name of the element without its namespace prefix, like `a` for `html:a`.
+    pub fn local_name(&self) -> &str {
+        match self.name.split_once(':') {
+            Some((_, local_name)) => local_name,
+            None => &self.name,
+        }
+    }
+}
+
+impl Pattern {
+    /// Add text to the end of the pattern, joining it with any text before it.
+    pub fn push_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.elements.last_mut() {
+            Some(PatternElement::Text(last)) => last.push_str(text),
+            _ => self.elements.push(PatternElement::Text(text.into())),
+        }
+    }
+
+    /// Add an element to the end of the pattern, joining any text with the text before
+    /// it.
+    pub fn push(&mut self, element: PatternElement) {
+        match element {
+            PatternElement::Text(text) => self.push_text(&text),
+            element => self.elements.push(element),
+        }
+    }
+
+    pub fn push_reference(&mut self, name: &str) {
+        self.elements
+            .push(PatternElement::EntityReference(name.into()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Check if the pattern contains any elements of markup.
+    pub fn has_markup(&self) -> bool {
+        self.elements.iter().any(|element| {
+            matches!(
+                element,
+                PatternElement::StartTag(_) | PatternElement::EndTag(_)
+            )
+        })
+    }
+
+    /// Iterate over the printf-style placeholders.
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.elements.iter().filter_map(|element| match element {
+            PatternElement::Placeholder(placeholder) => Some(placeholder),
+            _ => None,
+        })
+    }
+
+    /// Iterate over the names of the entities that are referenced.
+    pub fn references(&self) -> impl Iterator<Item = &str> {
+        self.elements.iter().filter_map(|element| match element {
+            PatternElement::EntityReference(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+}
+
+impl From<&str> for Pattern {
+    fn from(text: &str) -> Self {
+        let mut pattern = Pattern::default();
+        pattern.push_text(text);
+        pattern
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(text: String) -> Self {
+        Pattern::from(text.as_str())
+    }
+}
+
+/// Displays the pattern as it would be written in a DTD, which is useful in messages.
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for element in &self.elements {
+            match element {
+                PatternElement::Text(text) => write!(f, "{}", text)?,
+                PatternElement::EntityReference(name) => write!(f, "&{};", name)?,
+                PatternElement::StartTag(tag) => {
+                    write!(f, "<{}", tag.name)?;
+                    for (name, value) in &tag.attributes {
+                        write!(f, " {}=\"{}\"", name, value)?;
+                    }
+                    write!(f, "{}>", if tag.self_closing { "/" } else { "" })?;
+                }
+                PatternElement::EndTag(name) => write!(f, "</{}>", name)?,
+                PatternElement::Placeholder(placeholder) => write!(f, "{}", placeholder.source)?,
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a> From<Comment<'a>> for Node<'a> {
@@ -102,8 +605,260 @@ impl<'a> From<Comment<'a>> for Node<'a> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Comment<'a> {
-    pub key: Option<&'a str>,
+    /// The keys of the messages that a LOCALIZATION NOTE is about. Other comments have
+    /// no targets.
+    pub targets: Vec<NoteTarget<'a>>,
     pub value: String,
+    pub span: Span,
+    /// The span of the list of keys in a LOCALIZATION NOTE.
+    pub targets_span: Option<Span>,
+}
+
+impl<'a> Comment<'a> {
+    /// Check if this is a LOCALIZATION NOTE about some messages.
+    pub fn is_note(&self) -> bool {
+        !self.targets.is_empty()
+    }
+
+    /// Check if this is a LOCALIZATION NOTE about the message with the key.
+    pub fn is_note_for(&self, key: &str) -> bool {
+        self.targets.iter().any(|target| target.matches(key))
+    }
+
+    /// Check if the comment is directly above the next node, with no blank line between
+    /// them, and that node is a message, so that it's the comment of the message. The
+    /// comment has to start its line, as a comment after the previous node on the same
+    /// line is about that node. LOCALIZATION NOTEs name their messages instead.
+    pub fn is_attached(&self, previous: Option<&Node>, next: Option<&Node>) -> bool {
+        let starts_line =
+            previous.is_none_or(|previous| previous.span().end.line < self.span.start.line);
+        match next {
+            Some(Node::Message(message)) => {
+                !self.is_note() && starts_line && message.span.start.line == self.span.end.line + 1
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A message that a LOCALIZATION NOTE is about.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NoteTarget<'a> {
+    Key(&'a str),
+    /// A pattern of keys, where `*` matches any text, like `downloads.*`.
+    Wildcard(&'a str),
+}
+
+impl<'a> NoteTarget<'a> {
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            NoteTarget::Key(target) => *target == key,
+            NoteTarget::Wildcard(pattern) => {
+                let mut parts = pattern.split('*');
+                let first = parts.next().unwrap_or("");
+                let mut rest = match key.strip_prefix(first) {
+                    Some(rest) => rest,
+                    None => return false,
+                };
+                let mut parts: Vec<&str> = parts.collect();
+                let last = parts.pop().unwrap_or("");
+                for part in parts {
+                    match rest.find(part) {
+                        Some(index) => rest = &rest[index + part.len()..],
+                        None => return false,
+                    }
+                }
+                rest.ends_with(last)
+            }
+        }
+    }
+}
+
+impl<'a> From<Junk<'a>> for Node<'a> {
+    fn from(other: Junk<'a>) -> Self {
+        Node::Junk(other)
+    }
+}
+
+/// A region of the source that couldn't be parsed, and was skipped over.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Junk<'a> {
+    pub content: &'a str,
+    pub span: Span,
+    pub diagnostic: Diagnostic,
+}
+
+impl<'a> From<Include<'a>> for Node<'a> {
+    fn from(other: Include<'a>) -> Self {
+        Node::Include(other)
+    }
+}
+
+/// The declaration of an external parameter entity in a DTD, which includes another DTD
+/// where it's referenced.
+///
+/// ```text
+/// <!ENTITY % brandDTD SYSTEM "chrome://branding/locale/brand.dtd">
+/// %brandDTD;
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Include<'a> {
+    pub name: &'a str,
+    pub url: String,
+    /// The entity is referenced somewhere in the file, so the DTD is included.
+    pub referenced: bool,
+    pub span: Span,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_complete() {
+        let (nodes, diagnostics) = parse_complete(
+            dtd::dtd,
+            "<!ENTITY first \"First\">\n<!ENTITY second \"Second\">\n",
+        );
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(diagnostics, []);
+
+        let (nodes, diagnostics) = parse_complete(
+            properties::properties,
+            "first = First\n# Comment\nsecond = Second\n",
+        );
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(diagnostics, []);
+    }
+
+    #[test]
+    fn test_parse() {
+        let nodes = parse(properties::properties, "key = Value\n").unwrap();
+        assert_eq!(nodes.len(), 1);
+
+        let error = parse(properties::properties, "key = Value\n= No key\n")
+            .unwrap_err()
+            .with_path("file.properties");
+        assert_eq!(
+            error.to_string(),
+            "Unable to parse all of file.properties\n  file.properties:2:1: Unable to parse message_key"
+        );
+    }
+
+    #[test]
+    fn test_parse_complete_recovers() {
+        let source = "<!ENTITY first \"First\">\n  <!UNKNOWN foo>\n<!ENTITY second \"Second\">";
+        let (nodes, diagnostics) = parse_complete(dtd::dtd, source);
+        assert_eq!(
+            without_spans(nodes),
+            [
+                Message {
+                    key: "first".into(),
+                    value: "First".into(),
+                    ..Default::default()
+                }
+                .into(),
+                Junk {
+                    content: "<!UNKNOWN foo>\n",
+                    diagnostic: Diagnostic::new(source, 26, "Unable to parse entity".into()),
+                    ..Default::default()
+                }
+                .into(),
+                Message {
+                    key: "second".into(),
+                    value: "Second".into(),
+                    ..Default::default()
+                }
+                .into(),
+            ]
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "2:3: Unable to parse entity");
+    }
+
+    #[test]
+    fn test_localization_note() {
+        let note = |i| localization_note::<VerboseError<&str>>(i).map(|(_, note)| note);
+        assert_eq!(
+            note("LOCALIZATION NOTE (foo.label): Text"),
+            Ok(("foo.label", vec![NoteTarget::Key("foo.label")]))
+        );
+        assert_eq!(
+            note("LOCALIZATION NOTE (foo.label, foo.accesskey):"),
+            Ok((
+                "foo.label, foo.accesskey",
+                vec![
+                    NoteTarget::Key("foo.label"),
+                    NoteTarget::Key("foo.accesskey")
+                ]
+            ))
+        );
+        assert_eq!(
+            note("LOCALIZATION NOTE (downloads.*,other.key) Text"),
+            Ok((
+                "downloads.*,other.key",
+                vec![
+                    NoteTarget::Wildcard("downloads.*"),
+                    NoteTarget::Key("other.key")
+                ]
+            ))
+        );
+        assert!(note("LOCALIZATION NOTE (): Text").is_err());
+        assert!(note("LOCALIZATION NOTE (foo.label,): Text").is_err());
+    }
+
+    #[test]
+    fn test_comment_value() {
+        assert_eq!(comment_value(None, &[" One", " line  "]), "One\nline");
+        assert_eq!(
+            comment_value(None, &["", " First", "", "", " Second", ""]),
+            "First\n\nSecond"
+        );
+        assert_eq!(
+            comment_value(None, &[" Options:", "   - one", "     more", "   - two"]),
+            "Options:\n  - one\n    more\n  - two"
+        );
+        // The first line doesn't line up with the rest.
+        assert_eq!(
+            comment_value(Some(" Options:  "), &["     - one", "     - two"]),
+            "Options:\n- one\n- two"
+        );
+        assert_eq!(comment_value(Some(""), &["  ", "  Text"]), "Text");
+        assert_eq!(comment_value(None, &[]), "");
+    }
+
+    #[test]
+    fn test_note_target_matches() {
+        assert!(NoteTarget::Key("foo.label").matches("foo.label"));
+        assert!(!NoteTarget::Key("foo.label").matches("foo.labels"));
+        assert!(NoteTarget::Wildcard("downloads.*").matches("downloads.title"));
+        assert!(NoteTarget::Wildcard("downloads.*").matches("downloads."));
+        assert!(!NoteTarget::Wildcard("downloads.*").matches("download.title"));
+        assert!(NoteTarget::Wildcard("*.label").matches("back.label"));
+        assert!(!NoteTarget::Wildcard("*.label").matches("back.accesskey"));
+        assert!(NoteTarget::Wildcard("menu.*.label").matches("menu.file.label"));
+        assert!(!NoteTarget::Wildcard("menu.*.label").matches("menu.label"));
+        assert!(NoteTarget::Wildcard("a*b*c").matches("a-b-b-c"));
+    }
+
+    #[test]
+    fn test_parse_complete_stops() {
+        // A parser that only understands the first line.
+        let first_line =
+            |i: &'static str| properties::properties(&i[..14]).map(|(_, nodes)| (&i[14..], nodes));
+        let source = "first = First\nsecond = Second\n";
+        let (nodes, diagnostics) = parse_complete(first_line, source);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::new(
+                source,
+                14,
+                "Parsing stopped here, the rest of the file was not migrated: second = Second"
+                    .into()
+            )]
+        );
+    }
 }
@@ -1,7 +1,4 @@
-#![allow(unused_variables, dead_code)]
-
 use super::*;
-use crate::*;
 use nom::{
     //
     branch::*,
@@ -13,6 +10,7 @@ use nom::{
     sequence::*,
     *,
 };
+use std::borrow::Cow;
 
 // TODO - De-duplicate.
 #[cfg(test)]
@@ -34,26 +32,339 @@ macro_rules! parse {
     }};
 }
 
+/// Read up to 4 hex digits of a `\uXXXX` escape, returning the code unit and the number
+/// of bytes consumed. Like Gecko's parser, a short escape takes the digits that exist.
+fn unicode_escape(i: &str) -> (u16, usize) {
+    let digits = i
+        .bytes()
+        .take(4)
+        .take_while(|byte| byte.is_ascii_hexdigit())
+        .count();
+    let unit = u16::from_str_radix(&i[..digits], 16).unwrap_or(0);
+    (unit, digits)
+}
+
+/// Skip a single `\n`, `\r\n` or `\r` line terminator.
+fn skip_line_terminator(i: &str) -> &str {
+    if let Some(rest) = i.strip_prefix("\r\n") {
+        rest
+    } else if let Some(rest) = i.strip_prefix('\n') {
+        rest
+    } else if let Some(rest) = i.strip_prefix('\r') {
+        rest
+    } else {
+        i
+    }
+}
+
+/// Decode the escape sequence that follows a backslash. The escapes `\t`, `\n`, `\r`,
+/// `\f` and `\uXXXX` (including surrogate pairs) are decoded, and any other escaped
+/// character stands for itself. A backslash before a line terminator continues the
+/// logical line onto the next one, with the next line's leading whitespace stripped,
+/// in which case no character is returned.
+fn escape_sequence(i: &str) -> (Option<char>, &str) {
+    let escaped = match i.chars().next() {
+        Some(escaped) => escaped,
+        // A backslash at the end of the file is dropped.
+        None => return (None, i),
+    };
+    match escaped {
+        '\n' | '\r' => {
+            let rest = skip_line_terminator(i);
+            (None, rest.trim_start_matches(|c| " \t\x0C".contains(c)))
+        }
+        'u' => {
+            let (unit, length) = unicode_escape(&i[1..]);
+            let mut rest = &i[1 + length..];
+            if length == 0 {
+                // There are no hex digits, treat it as an escaped "u".
+                return (Some('u'), rest);
+            }
+            let mut units = vec![unit];
+            // A high surrogate needs to be combined with the following low surrogate
+            // escape to form a single character.
+            if (0xD800..0xDC00).contains(&unit) {
+                if let Some(next) = rest.strip_prefix("\\u") {
+                    let (low, length) = unicode_escape(next);
+                    if length == 4 && (0xDC00..0xE000).contains(&low) {
+                        units.push(low);
+                        rest = &next[length..];
+                    }
+                }
+            }
+            let c = std::char::decode_utf16(units)
+                .next()
+                .and_then(|c| c.ok())
+                .unwrap_or(std::char::REPLACEMENT_CHARACTER);
+            (Some(c), rest)
+        }
+        _ => {
+            let c = match escaped {
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                'f' => '\x0C',
+                other => other,
+            };
+            (Some(c), &i[escaped.len_utf8()..])
+        }
+    }
+}
+
+/// A key runs until the first unescaped separator, whitespace or line terminator. It
+/// may contain any other character, including escaped separators and whitespace. The
+/// key is only allocated when it needs to be unescaped.
+///
+/// browser.search.order\:1 = Google
+/// ^^^^^^^^^^^^^^^^^^^^^^^
 fn message_key<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, &str, E> {
-    take_while(|c: char| c.is_ascii_alphanumeric() || '.' == c || '-' == c)(i)
+) -> IResult<&'a str, Cow<'a, str>, E> {
+    let is_key_end = |c: char| " \t\x0C=:\r\n".contains(c);
+    let end = i
+        .find(|c: char| is_key_end(c) || c == '\\')
+        .unwrap_or(i.len());
+    if end == 0 && !i.starts_with('\\') {
+        return Err(Err::Error(E::add_context(
+            i,
+            "message_key",
+            E::from_error_kind(i, ErrorKind::TakeWhile1),
+        )));
+    }
+    if !i[end..].starts_with('\\') {
+        return Ok((&i[end..], Cow::Borrowed(&i[..end])));
+    }
+
+    let mut key = String::from(&i[..end]);
+    let mut rest = &i[end..];
+    while let Some(c) = rest.chars().next() {
+        if is_key_end(c) {
+            break;
+        }
+        if c == '\\' {
+            let (escaped, next) = escape_sequence(&rest[1..]);
+            key.extend(escaped);
+            rest = next;
+        } else {
+            key.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Ok((rest, Cow::Owned(key)))
+}
+
+#[test]
+fn test_message_key() {
+    let assert = |input, key: &str, after| {
+        assert_eq!(parse!(message_key, input), (after, Cow::from(key)));
+    };
+    let assert_err = |input| assert!(message_key::<()>(input).is_err());
+
+    assert("heapview.field.name=Group", "heapview.field.name", "=Group");
+    assert("under_score:value", "under_score", ":value");
+    assert("key value", "key", " value");
+    assert("key\tvalue", "key", "\tvalue");
+    assert("caf\u{E9}=Caf\u{E9}", "caf\u{E9}", "=Caf\u{E9}");
+    assert(
+        "escaped\\ space\\:colon\\=equals = value",
+        "escaped space:colon=equals",
+        " = value",
+    );
+    assert("\\u00e9t\\u00e9=summer", "\u{E9}t\u{E9}", "=summer");
+    assert("key", "key", "");
+    assert_err("=value");
+    assert_err(" key");
+    assert_err("");
+}
+
+/// Parse the value of a message up to the end of its logical line, following the
+/// .properties grammar. A line ending in a backslash continues onto the next line, and
+/// escapes are decoded, see `escape_sequence`. Trailing whitespace is trimmed unless it
+/// was escaped.
+///
+/// key = A long \
+///       value with a \u2026 character
+///       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+fn message_value<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, String, E> {
+    let mut value = String::new();
+    // The length in bytes of the unescaped whitespace at the end of the value.
+    let mut trailing_whitespace = 0;
+    let mut rest = i;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\n' | '\r' => {
+                rest = skip_line_terminator(rest);
+                break;
+            }
+            '\\' => {
+                let (escaped, next) = escape_sequence(&rest[1..]);
+                if let Some(escaped) = escaped {
+                    value.push(escaped);
+                    trailing_whitespace = 0;
+                }
+                rest = next;
+            }
+            _ => {
+                value.push(c);
+                if " \t\x0C".contains(c) {
+                    trailing_whitespace += c.len_utf8();
+                } else {
+                    trailing_whitespace = 0;
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    value.truncate(value.len() - trailing_whitespace);
+    Ok((rest, value))
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_message_value() {
+    let assert = |input, value: &str, after| {
+        assert_eq!(parse!(message_value, input), (after, value.to_string()));
+    };
+
+    assert("Simple value", "Simple value", "");
+    assert("Trailing whitespace  \nnext", "Trailing whitespace", "next");
+    assert("Windows line ending\r\nnext", "Windows line ending", "next");
+    assert("Escaped trailing space\\ \n", "Escaped trailing space ", "");
+    assert("\\ Escaped leading space", " Escaped leading space", "");
+    assert("Continued \\\n    onto the next line", "Continued onto the next line", "");
+    assert("Continued\\\r\n  with CRLF\nnext", "Continuedwith CRLF", "next");
+    assert("Many\\\n  \\\n  continuations", "Manycontinuations", "");
+    assert("Escaped backslash\\\\\nnext", "Escaped backslash\\", "next");
+    assert("\\t\\n\\r\\f", "\t\n\r\x0C", "");
+    assert("\\=\\:\\#\\!\\a", "=:#!a", "");
+    assert("Ellipsis\\u2026", "Ellipsis\u{2026}", "");
+    assert("\\u00A0\\u00e9", "\u{A0}\u{E9}", "");
+    assert("Short \\u41 escape", "Short A escape", "");
+    assert("Empty \\u escape", "Empty u escape", "");
+    assert("Emoji \\uD83D\\uDE00", "Emoji \u{1F600}", "");
+    assert("Lone \\uD83D surrogate", "Lone \u{FFFD} surrogate", "");
+    assert("Lone \\uDE00 surrogate", "Lone \u{FFFD} surrogate", "");
+    assert("Dangling backslash\\", "Dangling backslash", "");
+    assert("", "", "");
+}
+
+/// A printf-style placeholder, like `%S`, `%1$S`, `%ld` or `%.2f`, along with its
+/// explicit position. Positions start at 1, so `%0$S` isn't a placeholder.
+fn printf_placeholder<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, (&'a str, Option<usize>), E> {
+    map_opt(
+        consumed(tuple((
+            char('%'),                            // 0
+            opt(terminated(digit1, char('$'))),   // 1
+            opt(preceded(char('.'), digit1)),     // 2
+            take_while(|c| c == 'l' || c == 'h'), // 3
+            one_of("sSdiuxXfgc"),                 // 4
+        ))),
+        |(source, (_, position, _, _, _)): (_, (_, Option<&str>, _, _, _))| match position {
+            Some(position) => match position.parse() {
+                Ok(0) | Err(_) => None,
+                Ok(position) => Some((source, Some(position))),
+            },
+            None => Some((source, None)),
+        },
+    )(i)
+}
+
+/// Split the text of a value into text and printf-style placeholders. An escaped `%%`
+/// is a literal percent sign, and so is a `%` that doesn't start a placeholder.
+pub fn printf_pattern(text: &str) -> Pattern {
+    let mut pattern = Pattern::default();
+    // Placeholders without an explicit position take the next one.
+    let mut next_position = 1;
+    let mut rest = text;
+    while let Some(index) = rest.find('%') {
+        pattern.push_text(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("%%") {
+            pattern.push_text("%");
+            rest = after;
+            continue;
+        }
+        match printf_placeholder::<()>(rest) {
+            Ok((after, (source, position))) => {
+                let position = position.unwrap_or_else(|| {
+                    next_position += 1;
+                    next_position - 1
+                });
+                pattern.push(PatternElement::Placeholder(Placeholder {
+                    position,
+                    source: source.into(),
+                }));
+                rest = after;
+            }
+            Err(_) => {
+                pattern.push_text("%");
+                rest = &rest[1..];
+            }
+        }
+    }
+    pattern.push_text(rest);
+    pattern
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_printf_pattern() {
+    let placeholder = |position, source: &str| {
+        PatternElement::Placeholder(Placeholder { position, source: source.into() })
+    };
+    let text = |text: &str| PatternElement::Text(text.into());
+    let assert = |input, elements: Vec<PatternElement>| {
+        assert_eq!(printf_pattern(input), Pattern { elements });
+    };
+
+    assert("No placeholders", vec![text("No placeholders")]);
+    assert("", vec![]);
+    assert("Open %S", vec![text("Open "), placeholder(1, "%S")]);
+    assert(
+        "%S of %S",
+        vec![placeholder(1, "%S"), text(" of "), placeholder(2, "%S")],
+    );
+    assert(
+        "%2$S on %1$S",
+        vec![placeholder(2, "%2$S"), text(" on "), placeholder(1, "%1$S")],
+    );
+    assert(
+        "%d files, %ld bytes, %u%%",
+        vec![
+            placeholder(1, "%d"), text(" files, "), placeholder(2, "%ld"), text(" bytes, "),
+            placeholder(3, "%u"), text("%"),
+        ],
+    );
+    assert("%.2f MB", vec![placeholder(1, "%.2f"), text(" MB")]);
+    assert("100%% sure, 50% off, %", vec![text("100% sure, 50% off, %")]);
+    assert("%0$S items", vec![text("%0$S items")]);
 }
 
 fn message<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Message, E> {
+) -> IResult<&'a str, Message<'a>, E> {
     map(
         tuple((
             opt(single_line_whitespace), // 0
-            message_key,                 // 1
-            opt(single_line_whitespace), // 2
-            char('='),                   // 3
-            rest_of_line,                // 4
+            consumed(tuple((
+                consumed(message_key),       // 0
+                opt(single_line_whitespace), // 1
+                opt(one_of("=:")),           // 2
+                opt(single_line_whitespace), // 3
+                message_value,               // 4
+            ))), // 1
         )),
-        |tuple| Message {
-            key: tuple.1,
-            value: tuple.4.trim().into(),
+        |(_, (message, ((key_source, key), _, _, _, value)))| Message {
+            key,
+            value: Pattern::from(value),
+            span: Span::new(i, message.trim_end_matches(['\r', '\n'])),
+            key_span: Span::new(i, key_source),
         },
     )(i)
 }
@@ -69,7 +380,11 @@ fn test_message() {
     let assert = |expected: Test| {
         let actual = Test {
             input: expected.input,
-            comment: parse!(message, expected.input).1,
+            comment: Message {
+                span: Span::default(),
+                key_span: Span::default(),
+                ..parse!(message, expected.input).1
+            },
         };
         assert_eq!(expected, actual);
     };
@@ -77,64 +392,143 @@ fn test_message() {
     assert(Test {
         input: "heapview.field.name=Group",
         comment: Message {
-            key: "heapview.field.name",
+            key: "heapview.field.name".into(),
             value: "Group".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "heapview.field.name = Group",
         comment: Message {
-            key: "heapview.field.name",
+            key: "heapview.field.name".into(),
             value: "Group".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "key-prop = This is a long message \n# Comment",
         comment: Message {
-            key: "key-prop",
+            key: "key-prop".into(),
             value: "This is a long message".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "    key   =   value    ",
         comment: Message {
-            key: "key",
+            key: "key".into(),
+            value: "value".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "key = Multi-line \\\n    value \\u2026",
+        comment: Message {
+            key: "key".into(),
+            value: "Multi-line value \u{2026}".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "key: value",
+        comment: Message {
+            key: "key".into(),
+            value: "value".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "key value with spaces",
+        comment: Message {
+            key: "key".into(),
+            value: "value with spaces".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "key = value = with : separators",
+        comment: Message {
+            key: "key".into(),
+            value: "value = with : separators".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "empty.value",
+        comment: Message {
+            key: "empty.value".into(),
+            value: "".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "key = \\\n    value",
+        comment: Message {
+            key: "key".into(),
             value: "value".into(),
+            ..Default::default()
         },
     });
 }
 
 fn comment<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Comment, E> {
-    fold_many1(
-        map(
-            tuple((
-                opt(single_line_whitespace), // 0
-                tag("#"),                    // 1
-                opt(single_line_whitespace), // 2
-                opt(localization_note),      // 3
-                rest_of_line,                // 4
-            )),
-            |tuple| (tuple.3, tuple.4.trim()),
+) -> IResult<&'a str, Comment<'a>, E> {
+    let (rest, (source, (mut comment, targets_source))) = consumed(comment_lines)(i)?;
+    comment.span = Span::new(i, source.trim_end_matches(['\r', '\n']));
+    comment.targets_span = targets_source.map(|targets| Span::new(i, targets));
+    Ok((rest, comment))
+}
+
+/// The lines of a comment, along with the source of the keys of a LOCALIZATION NOTE.
+/// Each LOCALIZATION NOTE starts a comment of its own, so that the notes stacked in a
+/// block each keep their own keys.
+fn comment_lines<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, (Comment<'a>, Option<&'a str>), E> {
+    map(
+        pair(
+            comment_line,
+            many0(verify(comment_line, |(note, _)| note.is_none())),
         ),
-        Comment {
-            key: None,
-            value: String::new(),
-        },
-        |mut comment, tuple| {
-            if !comment.value.is_empty() {
-                comment.value.push_str(" ");
+        |((note, first_line), others)| {
+            let mut comment = Comment::default();
+            let mut lines: Vec<&str> = others.into_iter().map(|(_, line)| line).collect();
+            match note {
+                // The text of a note doesn't line up with the lines that follow it.
+                Some((source, targets)) => {
+                    comment.targets = targets;
+                    comment.value = comment_value(Some(first_line), &lines);
+                    (comment, Some(source))
+                }
+                None => {
+                    lines.insert(0, first_line);
+                    comment.value = comment_value(None, &lines);
+                    (comment, None)
+                }
             }
-            if let Some(key) = tuple.0 {
-                comment.key = Some(key);
-            }
-            comment.value.push_str(tuple.1);
-            comment
         },
     )(i)
 }
 
+/// The source of the keys of a LOCALIZATION NOTE, along with the keys.
+type Note<'a> = (&'a str, Vec<NoteTarget<'a>>);
+
+/// A line of a comment, along with the LOCALIZATION NOTE that it starts.
+fn comment_line<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, (Option<Note<'a>>, &'a str), E> {
+    map(
+        tuple((
+            opt(single_line_whitespace),                                   // 0
+            one_of("#!"),                                                  // 1
+            opt(preceded(opt(single_line_whitespace), localization_note)), // 2
+            rest_of_line,                                                  // 3
+        )),
+        |(_, _, note, line)| (note, line),
+    )(i)
+}
+
 #[test]
 fn test_comment() {
     #[derive(Debug, PartialEq)]
@@ -146,7 +540,11 @@ fn test_comment() {
     let assert = |expected: Test| {
         let actual = Test {
             input: expected.input,
-            comment: parse!(comment, expected.input).1,
+            comment: Comment {
+                span: Span::default(),
+                targets_span: None,
+                ..parse!(comment, expected.input).1
+            },
         };
         assert_eq!(expected, actual);
     };
@@ -154,74 +552,103 @@ fn test_comment() {
     assert(Test {
         input: "# multi\n# line\n",
         comment: Comment {
-            key: None,
-            value: "multi line".into(),
+            targets: vec![],
+            value: "multi\nline".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "# comment 1\n\n# comment 2\n",
         comment: Comment {
-            key: None,
+            targets: vec![],
             value: "comment 1".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "# Comment",
         comment: Comment {
-            key: None,
+            targets: vec![],
             value: "Comment".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "#   Whitespace  ",
         comment: Comment {
-            key: None,
+            targets: vec![],
             value: "Whitespace".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "# LOCALIZATION NOTE (key.value) Comment",
         comment: Comment {
-            key: Some("key.value"),
+            targets: vec![NoteTarget::Key("key.value")],
             value: "Comment".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "# LOCALIZATION NOTE (key.value): The first paragraph,\n#   which wraps.\n#\n#   - A list\n#     item.\n#   - Another item.\n",
+        comment: Comment {
+            targets: vec![NoteTarget::Key("key.value")],
+            value: "The first paragraph,\nwhich wraps.\n\n- A list\n  item.\n- Another item.".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "# LOCALIZATION NOTE (key.label, key.accesskey, other.*): Comment",
+        comment: Comment {
+            targets: vec![
+                NoteTarget::Key("key.label"),
+                NoteTarget::Key("key.accesskey"),
+                NoteTarget::Wildcard("other.*"),
+            ],
+            value: "Comment".into(),
+            ..Default::default()
+        },
+    });
+    assert(Test {
+        input: "# LOCALIZATION NOTE (first) First note.\n# LOCALIZATION NOTE (second) Second note.",
+        comment: Comment {
+            targets: vec![NoteTarget::Key("first")],
+            value: "First note.".into(),
+            ..Default::default()
         },
     });
     assert(Test {
         input: "# LOCALIZATION NOTE (key.value) Multi-line\n# comment.",
         comment: Comment {
-            key: Some("key.value"),
-            value: "Multi-line comment.".into(),
+            targets: vec![NoteTarget::Key("key.value")],
+            value: "Multi-line\ncomment.".into(),
+            ..Default::default()
         },
     });
 }
 
-pub fn properties<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+fn entry<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     i: &'a str,
-) -> IResult<&'a str, Vec<Node>, E> {
-    // alt((
-    //     map(message, |message| Node::Message(message)),
-    //     map(comment, |comment| Node::Comment(comment)),
-    // ))(i)
-
-    context(
-        "properties",
-        fold_many0(
-            tuple((
-                opt(whitespace), // 0
-                alt((
-                    map(comment, |comment| Node::Comment(comment)),
-                    map(message, |message| Node::Message(message)),
-                )), // 1
-            )),
-            Vec::new(),
-            |mut nodes: Vec<_>, tuple| {
-                nodes.push(tuple.1);
-                nodes
-            },
-        ),
+) -> IResult<&'a str, Option<Node<'a>>, E> {
+    map(
+        alt((map(comment, Node::Comment), map(message, Node::Message))),
+        Some,
     )(i)
 }
 
+/// The next entry starts on the next line.
+fn entry_boundary(i: &str) -> usize {
+    i.find('\n').map_or(i.len(), |index| index + 1)
+}
+
+/// Parse all of the entries in a .properties file. A line that isn't understood is
+/// kept as a Junk node, so this never fails.
+pub fn properties<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, Vec<Node<'a>>, E> {
+    Ok(("", entries(i, entry, entry_boundary)))
+}
+
 #[test]
 fn test_properties() {
     let text = "# This Source Code Form is subject to the terms of the Mozilla Public
@@ -241,35 +668,181 @@ snapshot.io.delete=Delete
 snapshot.io.save.window=Save Snapshot
     ";
     let (rest_text, nodes) = parse!(properties, text);
+    assert_eq!(rest_text, "");
 
-    assert_eq!(nodes, [
+    assert_eq!(without_spans(nodes), [
         Comment {
-            key: None,
-            value: "This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed with this file, You can obtain one at http://mozilla.org/MPL/2.0/.".into(),
+            targets: vec![],
+            value: "This Source Code Form is subject to the terms of the Mozilla Public\nLicense, v. 2.0. If a copy of the MPL was not distributed with this\nfile, You can obtain one at http://mozilla.org/MPL/2.0/.".into(),
+            ..Default::default()
         }.into(),
         Comment {
-            key: Some("snapshot.io.save"),
-            value: "The label for the link that saves a snapshot to disk.".into(),
+            targets: vec![NoteTarget::Key("snapshot.io.save")],
+            value: "The label for the link that saves a\nsnapshot to disk.".into(),
+            ..Default::default()
         }.into(),
         Message {
-            key: "snapshot.io.save",
+            key: "snapshot.io.save".into(),
             value: "Save".into(),
+            ..Default::default()
         }.into(),
         Comment {
-            key: Some("snapshot.io.delete"),
-            value: "The label for the link that deletes a snapshot".into(),
+            targets: vec![NoteTarget::Key("snapshot.io.delete")],
+            value: "The label for the link that deletes\na snapshot".into(),
+            ..Default::default()
         }.into(),
         Message {
-            key: "snapshot.io.delete",
+            key: "snapshot.io.delete".into(),
             value: "Delete".into(),
+            ..Default::default()
         }.into(),
         Comment {
-            key: Some("snapshot.io.save.window"),
-            value: "The title for the window displayed when saving a snapshot to disk.".into(),
+            targets: vec![NoteTarget::Key("snapshot.io.save.window")],
+            value: "The title for the window\ndisplayed when saving a snapshot to disk.".into(),
+            ..Default::default()
         }.into(),
         Message {
-            key: "snapshot.io.save.window",
+            key: "snapshot.io.save.window".into(),
             value: "Save Snapshot".into(),
+            ..Default::default()
         }.into()
     ]);
 }
+
+#[test]
+fn test_properties_syntax() {
+    let text = "! Exclamation comment
+under_score=1
+colon : 2
+whitespace 3
+escaped\\ key\\:name=4
+\u{E9}t\u{E9}=5
+# Trailing comment
+";
+    let (rest_text, nodes) = parse!(properties, text);
+
+    assert_eq!(rest_text, "");
+    assert_eq!(
+        without_spans(nodes),
+        [
+            Comment {
+                targets: vec![],
+                value: "Exclamation comment".into(),
+                ..Default::default()
+            }
+            .into(),
+            Message {
+                key: "under_score".into(),
+                value: "1".into(),
+                ..Default::default()
+            }
+            .into(),
+            Message {
+                key: "colon".into(),
+                value: "2".into(),
+                ..Default::default()
+            }
+            .into(),
+            Message {
+                key: "whitespace".into(),
+                value: "3".into(),
+                ..Default::default()
+            }
+            .into(),
+            Message {
+                key: "escaped key:name".into(),
+                value: "4".into(),
+                ..Default::default()
+            }
+            .into(),
+            Message {
+                key: "\u{E9}t\u{E9}".into(),
+                value: "5".into(),
+                ..Default::default()
+            }
+            .into(),
+            Comment {
+                targets: vec![],
+                value: "Trailing comment".into(),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    );
+}
+
+#[test]
+fn test_properties_junk() {
+    let text = "first = First\n= No key\nsecond = Second\n";
+    let (rest_text, nodes) = parse!(properties, text);
+
+    assert_eq!(rest_text, "");
+    assert_eq!(
+        without_spans(nodes),
+        [
+            Message {
+                key: "first".into(),
+                value: "First".into(),
+                ..Default::default()
+            }
+            .into(),
+            Junk {
+                content: "= No key\n",
+                diagnostic: Diagnostic::new(text, 14, "Unable to parse message_key".into()),
+                ..Default::default()
+            }
+            .into(),
+            Message {
+                key: "second".into(),
+                value: "Second".into(),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    );
+}
+
+#[test]
+fn test_spans() {
+    let source = "# LOCALIZATION NOTE (first): Note
+first = First
+  second.key\\u00e9 = Multi-line \\
+    value
+";
+    // The text of the span, then its start and end line and column.
+    let span = |span: Span| {
+        (
+            &source[span.range()],
+            (span.start.line, span.start.column),
+            (span.end.line, span.end.column),
+        )
+    };
+    let (_, nodes) = parse!(properties, source);
+    match &nodes[..] {
+        [Node::Comment(comment), Node::Message(first), Node::Message(second)] => {
+            assert_eq!(
+                span(comment.span),
+                ("# LOCALIZATION NOTE (first): Note", (1, 1), (1, 34))
+            );
+            assert_eq!(
+                span(comment.targets_span.unwrap()),
+                ("first", (1, 22), (1, 27))
+            );
+            assert_eq!(span(first.span), ("first = First", (2, 1), (2, 14)));
+            assert_eq!(span(first.key_span), ("first", (2, 1), (2, 6)));
+            assert_eq!(
+                span(second.span),
+                (
+                    "second.key\\u00e9 = Multi-line \\\n    value",
+                    (3, 3),
+                    (4, 10)
+                )
+            );
+            assert_eq!(
+                span(second.key_span),
+                ("second.key\\u00e9", (3, 3), (3, 19))
+            );
+        }
+        _ => panic!("Unexpected nodes {:#?}", nodes),
+    }
+}
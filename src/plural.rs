@@ -0,0 +1,139 @@
+use crate::parser::{Pattern, PatternElement, Placeholder};
+
+/// The CLDR plural categories of the forms of each plural rule of `PluralForm.jsm`, in
+/// the order that the forms are written in a string. The rules are listed with the
+/// locales that use them.
+const PLURAL_RULES: &[(&[&str], &[&str])] = &[
+    // Rule 0, everything is the same form.
+    (&["ja", "ko", "zh", "th", "vi", "id", "ms"], &["other"]),
+    // Rule 1, one form for 1 and one for everything else.
+    (
+        &[
+            "en", "de", "nl", "it", "es", "pt", "sv", "da", "nb", "nn", "fi", "el", "he", "hu",
+            "et", "bg", "ca", "eu", "gl", "tr",
+        ],
+        &["one", "other"],
+    ),
+    // Rule 2, one form for 0 and 1, and one for everything else.
+    (&["fr", "pt-BR"], &["one", "other"]),
+    // Rule 3, Latvian.
+    (&["lv"], &["zero", "one", "other"]),
+    // Rule 5, Romanian.
+    (&["ro"], &["one", "few", "other"]),
+    // Rule 7, the Slavic languages that end in 1, 2-4 and the rest.
+    (
+        &["ru", "uk", "be", "sr", "hr", "bs"],
+        &["one", "few", "many"],
+    ),
+    // Rule 8, Czech and Slovak.
+    (&["cs", "sk"], &["one", "few", "other"]),
+    // Rule 9, Polish.
+    (&["pl"], &["one", "few", "many"]),
+    // Rule 10, Slovenian.
+    (&["sl"], &["one", "two", "few", "other"]),
+    // Rule 12, Arabic. The forms are in the order of the rule, which puts zero last.
+    (&["ar"], &["one", "two", "few", "many", "other", "zero"]),
+];
+
+/// The CLDR plural categories of the plural forms that a locale writes, in order. The
+/// locale is matched exactly, and then by its language.
+pub fn plural_categories(locale: &str) -> Option<&'static [&'static str]> {
+    let find = |locale: &str| {
+        PLURAL_RULES
+            .iter()
+            .find(|(locales, _)| locales.iter().any(|l| l.eq_ignore_ascii_case(locale)))
+            .map(|(_, categories)| *categories)
+    };
+    let language = locale.split(['-', '_']).next().unwrap_or(locale);
+    find(locale).or_else(|| find(language))
+}
+
+/// Check if a comment says that a message is a list of plural forms, like the
+/// "Semicolon list of plural forms" notes of `PluralForm.jsm` strings.
+pub fn is_plural_note(comment: &str) -> bool {
+    let comment = comment.to_lowercase();
+    comment.contains("plural form")
+        || comment.contains("pluralform")
+        || comment.contains("localization_and_plurals")
+}
+
+/// Split the value of a plural message into its forms, which are separated by
+/// semicolons. Each form is a string of its own, so the placeholders without an explicit
+/// position, like `%S`, are numbered from the start of their form.
+pub fn split_variants(pattern: &Pattern) -> Vec<Pattern> {
+    let mut variants = vec![Pattern::default()];
+    let mut next_position = 1;
+    for element in &pattern.elements {
+        match element {
+            PatternElement::Text(text) => {
+                let mut parts = text.split(';');
+                if let (Some(part), Some(variant)) = (parts.next(), variants.last_mut()) {
+                    variant.push_text(part);
+                }
+                for part in parts {
+                    variants.push(Pattern::from(part));
+                    next_position = 1;
+                }
+            }
+            PatternElement::Placeholder(placeholder) if !placeholder.source.contains('$') => {
+                if let Some(variant) = variants.last_mut() {
+                    variant.push(PatternElement::Placeholder(Placeholder {
+                        position: next_position,
+                        source: placeholder.source.clone(),
+                    }));
+                    next_position += 1;
+                }
+            }
+            element => {
+                if let Some(variant) = variants.last_mut() {
+                    variant.push(element.clone());
+                }
+            }
+        }
+    }
+    variants
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plural_categories() {
+        assert_eq!(plural_categories("en-US"), Some(&["one", "other"][..]));
+        assert_eq!(plural_categories("pt-BR"), Some(&["one", "other"][..]));
+        assert_eq!(plural_categories("ru"), Some(&["one", "few", "many"][..]));
+        assert_eq!(plural_categories("ja_JP"), Some(&["other"][..]));
+        assert_eq!(
+            plural_categories("ar"),
+            Some(&["one", "two", "few", "many", "other", "zero"][..])
+        );
+        assert_eq!(plural_categories("xx"), None);
+    }
+
+    #[test]
+    fn test_split_variants() {
+        let pattern = crate::parser::properties::printf_pattern("#1 file in %S;#1 files in %S");
+        let variants: Vec<String> = split_variants(&pattern)
+            .iter()
+            .map(|variant| variant.to_string())
+            .collect();
+        assert_eq!(variants, ["#1 file in %S", "#1 files in %S"]);
+        let positions: Vec<Vec<usize>> = split_variants(&pattern)
+            .iter()
+            .map(|variant| variant.placeholders().map(|p| p.position).collect())
+            .collect();
+        assert_eq!(positions, [[1], [1]]);
+        assert_eq!(split_variants(&Pattern::from("One")).len(), 1);
+        assert_eq!(split_variants(&Pattern::from("")), [Pattern::default()]);
+    }
+
+    #[test]
+    fn test_is_plural_note() {
+        assert!(is_plural_note(
+            "Semicolon list of plural forms. See: http://developer.mozilla.org/en/docs/Localization_and_Plurals"
+        ));
+        assert!(is_plural_note("This string uses PluralForm.get()."));
+        assert!(!is_plural_note("The number of files."));
+    }
+}
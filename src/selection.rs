@@ -0,0 +1,407 @@
+use crate::error::Error;
+use crate::parser::{cst::Cst, cst::Element, Node};
+use regex::Regex;
+use std::{collections::HashSet, ops::Range};
+
+/// Chooses the messages to migrate in a partial migration. A key is selected if it
+/// matches any of the keys, prefixes or patterns.
+///
+/// ```
+/// use fluent_migrator::KeySelection;
+///
+/// let selection = KeySelection::new()
+///     .key("downloads.title")
+///     .prefix("downloads.panel.")
+///     .pattern(r"^download\d+$")
+///     .unwrap();
+/// assert!(selection.matches("downloads.panel.label"));
+/// assert!(!selection.matches("history.title"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeySelection {
+    keys: HashSet<String>,
+    prefixes: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl KeySelection {
+    pub fn new() -> Self {
+        KeySelection::default()
+    }
+
+    /// Select a key exactly.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.keys.insert(key.into());
+        self
+    }
+
+    /// Select every key that starts with the prefix.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Select every key that matches the regular expression.
+    pub fn pattern(mut self, pattern: &str) -> Result<Self, Error> {
+        let regex = Regex::new(pattern).map_err(|error| Error::InvalidPattern {
+            pattern: pattern.into(),
+            error,
+        })?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        self.keys.contains(key)
+            || self.prefixes.iter().any(|prefix| key.starts_with(prefix))
+            || self.patterns.iter().any(|pattern| pattern.is_match(key))
+    }
+
+    /// Check if a node is migrated with the selection. These are the selected messages,
+    /// the comments attached to them, and the LOCALIZATION NOTE comments about any of
+    /// them. The previous and next nodes are the ones around it in the file, and the
+    /// keys are those of all of the messages in the file.
+    pub fn migrates(
+        &self,
+        node: &Node,
+        previous: Option<&Node>,
+        next: Option<&Node>,
+        keys: &[&str],
+    ) -> bool {
+        match node {
+            Node::Message(message) => self.matches(&message.key),
+            Node::Comment(comment) if comment.is_attached(previous, next) => self.selects(next),
+            Node::Comment(comment) => keys
+                .iter()
+                .any(|key| comment.is_note_for(key) && self.matches(key)),
+            Node::Junk(_) | Node::Include(_) => false,
+        }
+    }
+
+    /// Check if a node is removed from the source with the selection. These are the
+    /// selected messages, the comments attached to them, and the LOCALIZATION NOTE
+    /// comments that are only about selected messages, as the rest still need their
+    /// notes.
+    pub fn removes(
+        &self,
+        node: &Node,
+        previous: Option<&Node>,
+        next: Option<&Node>,
+        keys: &[&str],
+    ) -> bool {
+        match node {
+            Node::Message(message) => self.matches(&message.key),
+            Node::Comment(comment) if comment.is_attached(previous, next) => self.selects(next),
+            Node::Comment(comment) => {
+                let mut noted_keys = keys
+                    .iter()
+                    .filter(|key| comment.is_note_for(key))
+                    .peekable();
+                noted_keys.peek().is_some() && noted_keys.all(|key| self.matches(key))
+            }
+            Node::Junk(_) | Node::Include(_) => false,
+        }
+    }
+
+    /// Check if a node is a selected message.
+    fn selects(&self, node: Option<&Node>) -> bool {
+        matches!(node, Some(Node::Message(message)) if self.matches(&message.key))
+    }
+}
+
+/// The keys of all of the messages in a file.
+pub fn message_keys<'a>(cst: &'a Cst) -> Vec<&'a str> {
+    cst.nodes()
+        .filter_map(|node| match node {
+            Node::Message(message) => Some(&*message.key),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Rewrite the source of a file without the nodes that are selected, leaving everything
+/// else untouched. Whole lines are removed, so the indentation and line ending of each
+/// removed node go with it, and a blank line is removed when the surrounding blank
+/// lines would otherwise double up.
+pub fn remove_selected(cst: &Cst, selection: &KeySelection) -> String {
+    let source: String = cst.elements.iter().map(Element::text).collect();
+    kept_ranges(cst, selection)
+        .into_iter()
+        .map(|range| &source[range])
+        .collect()
+}
+
+/// The ranges of the source that `remove_selected` keeps, in order.
+pub fn kept_ranges(cst: &Cst, selection: &KeySelection) -> Vec<Range<usize>> {
+    let keys = message_keys(cst);
+    let nodes: Vec<&Node> = cst.nodes().collect();
+    // The number of nodes so far, which is the index of the node after the current one.
+    let mut node_count: usize = 0;
+    let mut kept = Kept::default();
+    // Set after removing a node, until the end of its line has been removed too. It
+    // stays set when nothing follows the removed node.
+    let mut removing_line_end = false;
+    let mut offset = 0;
+    for element in &cst.elements {
+        let start = offset;
+        offset += element.text().len();
+        if let Element::Node { .. } = element {
+            node_count += 1;
+        }
+        match element {
+            Element::Node { node, .. }
+                if selection.removes(
+                    node,
+                    node_count.checked_sub(2).map(|i| nodes[i]),
+                    nodes.get(node_count).copied(),
+                    &keys,
+                ) =>
+            {
+                // Remove the indentation before the node.
+                let text = &kept.text;
+                let indentation = text.len() - text.trim_end_matches([' ', '\t']).len();
+                if text.is_empty() || text[..text.len() - indentation].ends_with('\n') {
+                    kept.truncate(text.len() - indentation);
+                }
+                removing_line_end = true;
+            }
+            Element::Whitespace(whitespace) if removing_line_end => {
+                let mut rest = skip_line(whitespace).unwrap_or(whitespace);
+                // Don't leave two blank lines where the removed lines were.
+                let text = &kept.text;
+                if text.is_empty() || text.ends_with("\n\n") || text.ends_with("\n\r\n") {
+                    rest = skip_line(rest).unwrap_or(rest);
+                }
+                kept.push(rest, offset - rest.len());
+                removing_line_end = rest.is_empty();
+            }
+            element => {
+                removing_line_end = false;
+                kept.push(element.text(), start);
+            }
+        }
+    }
+    if removing_line_end {
+        // The end of the file was removed, don't leave blank lines behind.
+        let content_end = kept.text.trim_end().len();
+        if let Some(line_end) = kept.text[content_end..].find('\n') {
+            kept.truncate(content_end + line_end + 1);
+        }
+    }
+    kept.ranges
+}
+
+/// The text that is kept so far, along with the ranges of the source it came from.
+#[derive(Default)]
+struct Kept {
+    text: String,
+    ranges: Vec<Range<usize>>,
+}
+
+impl Kept {
+    /// Keep the text, which starts at the offset in the source.
+    fn push(&mut self, text: &str, start: usize) {
+        if text.is_empty() {
+            return;
+        }
+        self.text.push_str(text);
+        match self.ranges.last_mut() {
+            Some(range) if range.end == start => range.end += text.len(),
+            _ => self.ranges.push(start..start + text.len()),
+        }
+    }
+
+    /// Drop the end of the kept text.
+    fn truncate(&mut self, len: usize) {
+        let mut removed = self.text.len() - len;
+        self.text.truncate(len);
+        while let Some(range) = self.ranges.last_mut() {
+            let from_range = removed.min(range.len());
+            range.end -= from_range;
+            removed -= from_range;
+            if range.start == range.end {
+                self.ranges.pop();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Skip past the end of the current line, if there is nothing but whitespace on it.
+fn skip_line(text: &str) -> Option<&str> {
+    let line_end = text.find('\n')?;
+    if text[..line_end].trim().is_empty() {
+        Some(&text[line_end + 1..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{Dtd, Properties, SourceFormat};
+
+    fn remove(format: &dyn SourceFormat, source: &str, selection: &KeySelection) -> String {
+        let (cst, _) = format.parse_cst(source);
+        remove_selected(&cst, selection)
+    }
+
+    #[test]
+    fn test_matches() {
+        let selection = KeySelection::new()
+            .key("exact")
+            .prefix("prefix.")
+            .pattern("^pattern[0-9]$")
+            .unwrap();
+        assert!(selection.matches("exact"));
+        assert!(!selection.matches("exact.not"));
+        assert!(selection.matches("prefix.key"));
+        assert!(!selection.matches("prefix"));
+        assert!(selection.matches("pattern1"));
+        assert!(!selection.matches("pattern10"));
+        assert!(KeySelection::new().pattern("(").is_err());
+    }
+
+    #[test]
+    fn test_remove_properties() {
+        let source = "# License
+
+# LOCALIZATION NOTE (first): The note.
+first = First
+second = Second
+
+# LOCALIZATION NOTE (third): The note.
+third = Third
+
+fourth = Fourth
+";
+        let selection = KeySelection::new().key("first").key("third");
+        assert_eq!(
+            remove(&Properties, source, &selection),
+            "# License
+
+second = Second
+
+fourth = Fourth
+"
+        );
+
+        let selection = KeySelection::new().key("fourth");
+        assert_eq!(
+            remove(&Properties, source, &selection),
+            "# License
+
+# LOCALIZATION NOTE (first): The note.
+first = First
+second = Second
+
+# LOCALIZATION NOTE (third): The note.
+third = Third
+"
+        );
+    }
+
+    #[test]
+    fn test_remove_dtd() {
+        let source = "<!-- License -->
+
+<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">
+%brandDTD;
+
+  <!-- LOCALIZATION NOTE (back.label): The note. -->
+  <!ENTITY back.label \"Back\">
+  <!ENTITY forward.label \"Forward\">\r
+<!ENTITY stop.label \"Stop\">
+";
+        let selection = KeySelection::new().prefix("back.").key("stop.label");
+        assert_eq!(
+            remove(&Dtd, source, &selection),
+            "<!-- License -->
+
+<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">
+%brandDTD;
+
+  <!ENTITY forward.label \"Forward\">\r
+"
+        );
+    }
+
+    #[test]
+    fn test_remove_shared_notes() {
+        let source = "# LOCALIZATION NOTE (back.label, back.accesskey): The note.
+back.label = Back
+back.accesskey = B
+# LOCALIZATION NOTE (forward.*): The note.
+forward.label = Forward
+";
+        let selection = KeySelection::new().key("back.label").key("forward.label");
+        assert_eq!(
+            remove(&Properties, source, &selection),
+            "# LOCALIZATION NOTE (back.label, back.accesskey): The note.
+back.accesskey = B
+"
+        );
+    }
+
+    #[test]
+    fn test_remove_stacked_notes() {
+        let source = "# LOCALIZATION NOTE (a.title): First note.
+# LOCALIZATION NOTE (b.title): Second note.
+a.title = A
+b.title = B
+";
+        assert_eq!(
+            remove(&Properties, source, &KeySelection::new().key("a.title")),
+            "# LOCALIZATION NOTE (b.title): Second note.
+b.title = B
+"
+        );
+    }
+
+    #[test]
+    fn test_remove_attached_comments() {
+        let source = "# License
+
+# The comment of first.
+first = First
+# The comment of second.
+second = Second
+";
+        let selection = KeySelection::new().key("first");
+        assert_eq!(
+            remove(&Properties, source, &selection),
+            "# License
+
+# The comment of second.
+second = Second
+"
+        );
+        // A comment after a message on the same line isn't about the next message.
+        assert_eq!(
+            remove(
+                &Dtd,
+                "<!ENTITY a.label \"A\"> <!-- About a. -->\n<!ENTITY b.label \"B\">\n",
+                &KeySelection::new().key("b.label")
+            ),
+            "<!ENTITY a.label \"A\"> <!-- About a. -->\n"
+        );
+
+        let selection = KeySelection::new().key("second");
+        assert_eq!(
+            remove(&Properties, source, &selection),
+            "# License
+
+# The comment of first.
+first = First
+"
+        );
+    }
+
+    #[test]
+    fn test_remove_nothing() {
+        let source = "# Comment\n\nkey = Value\n";
+        assert_eq!(remove(&Properties, source, &KeySelection::new()), source);
+    }
+}
@@ -0,0 +1,613 @@
+use fluent_migrator::{
+    nodes_to_fluent, parse_dtd, parse_properties, Diagnostic, Dtd, Error, IncludedDtd,
+    KeySelection, Message, Migrator, Node, Pattern, PatternElement, Placeholder, Properties,
+    SourceFormat, SourceRoot, Span,
+};
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// An empty directory for a test to write files into, removed when the test ends.
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("fluent-migrator-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+
+    /// Write a file in the directory, creating its parents, and return its path.
+    fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Assert that the text ends with `end`, showing the whole text if it doesn't.
+fn assert_ends_with(text: &str, end: &str) {
+    assert!(text.ends_with(end), "{}", text);
+}
+
+#[test]
+fn test_parse_dtd() {
+    let nodes = parse_dtd("<!-- Comment -->\n<!ENTITY back.label \"Back\">\n").unwrap();
+    assert_eq!(nodes.len(), 2);
+    match &nodes[1] {
+        Node::Message(Message { key, value, .. }) => {
+            assert_eq!(key, "back.label");
+            assert_eq!(value.to_string(), "Back");
+        }
+        node => panic!("Expected a message, got {:?}", node),
+    }
+}
+
+#[test]
+fn test_parse_properties() {
+    let nodes = parse_properties("# Comment\nback.label = Back\n").unwrap();
+    assert_eq!(nodes.len(), 2);
+
+    match parse_properties("back.label = Back\n= No key\n") {
+        Err(Error::Parse { path, diagnostics }) => {
+            assert_eq!(path, None);
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].position.line, 2);
+        }
+        result => panic!("Expected a parse error, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_nodes_to_fluent() {
+    let nodes = parse_properties("back.label = Back\n").unwrap();
+    let fluent = nodes_to_fluent(&nodes);
+    assert_ends_with(&fluent, "back-label = Back\n");
+}
+
+#[test]
+fn test_migrate() {
+    let migration = Migrator::new()
+        .migrate("back.label = Back\n", &Properties)
+        .unwrap();
+    assert_ends_with(&migration.fluent, "back-label = Back\n");
+    assert_eq!(migration.diagnostics, []);
+}
+
+#[test]
+fn test_migrate_multiline() {
+    let migration = Migrator::new()
+        .migrate(
+            "<!ENTITY intro.text 'The \"first\" line\r\n\r\n    An indented line'>\n",
+            &Dtd,
+        )
+        .unwrap();
+    assert_ends_with(
+        &migration.fluent,
+        "intro-text =\n  The \"first\" line\n\n      An indented line\n",
+    );
+}
+
+#[test]
+fn test_migrate_references() {
+    let migration = Migrator::new()
+        .migrate("<!ENTITY size \"10&#160;MB &amp; more&#x2026;\">\n", &Dtd)
+        .unwrap();
+    assert_ends_with(&migration.fluent, "size = 10{ \"\\u00A0\" }MB & more…\n");
+}
+
+#[test]
+fn test_migrate_terms() {
+    let source = "<!ENTITY about \"About &brandShortName;\">\n<!ENTITY by \"By &vendor;\">\n";
+    let migration = Migrator::new().migrate(source, &Dtd).unwrap();
+    assert!(migration
+        .fluent
+        .contains("about = About { -brand-short-name }\n"));
+    assert!(migration.fluent.contains("by = By { -vendor }\n"));
+    assert_eq!(migration.warnings.len(), 1);
+    assert_eq!(migration.warnings[0].position.line, 2);
+
+    let migration = Migrator::new()
+        .term("vendor", "-vendor-short-name")
+        .migrate(source, &Dtd)
+        .unwrap();
+    assert!(migration
+        .fluent
+        .contains("by = By { -vendor-short-name }\n"));
+    assert_eq!(migration.warnings, []);
+}
+
+#[test]
+fn test_migrate_placeholders() {
+    let source = "# LOCALIZATION NOTE (status): %1$S is the file name, %2$S the size.\nstatus = %2$S of %1$S, %3$d%% done\n";
+    let migration = Migrator::new().migrate(source, &Properties).unwrap();
+    assert!(
+        migration
+            .fluent
+            .contains("status = { $size } of { $fileName }, { $var3 }% done\n"),
+        "{}",
+        migration.fluent
+    );
+    assert_eq!(migration.warnings.len(), 1);
+
+    let migration = Migrator::new()
+        .fallback_variable("file")
+        .fallback_variable("bytes")
+        .fallback_variable("percent")
+        .migrate("status = %2$S of %1$S, %3$d%% done\n", &Properties)
+        .unwrap();
+    assert!(migration
+        .fluent
+        .contains("status = { $bytes } of { $file }, { $percent }% done\n"));
+    assert_eq!(migration.warnings, []);
+}
+
+#[test]
+fn test_migrate_plurals() {
+    let source = "# LOCALIZATION NOTE (downloads.files): Semicolon list of plural forms.\ndownloads.files = #1 file;#1 files\nother.files = #1 file;#1 files\n";
+    let migration = Migrator::new().migrate(source, &Properties).unwrap();
+    assert!(
+        migration.fluent.contains(
+            "downloads-files =\n  { $count ->\n    [one] { $count } file\n   *[other] { $count } files\n  }\n"
+        ),
+        "{}",
+        migration.fluent
+    );
+    assert!(migration
+        .fluent
+        .contains("other-files = #1 file;#1 files\n"));
+
+    let migration = Migrator::new()
+        .plurals(KeySelection::new().prefix("other."))
+        .source_locale("fr")
+        .unwrap()
+        .migrate(source, &Properties)
+        .unwrap();
+    assert!(migration
+        .fluent
+        .contains("other-files =\n  { $count ->\n    [one] { $count } file\n   *[other] { $count } files\n  }\n"));
+    assert!(matches!(
+        Migrator::new().source_locale("xx"),
+        Err(Error::UnsupportedLocale(_))
+    ));
+}
+
+#[test]
+fn test_migrate_attributes() {
+    let source = "<!-- LOCALIZATION NOTE (saveButton.label, saveButton.accesskey): The save button. -->\n<!ENTITY saveButton.label \"Save\">\n<!ENTITY saveButton.accesskey \"S\">\n<!ENTITY saveButton.tooltiptext \"Save the page\">\n";
+    let migration = Migrator::new().migrate(source, &Dtd).unwrap();
+    assert_ends_with(
+        &migration.fluent,
+        "# The save button.\nsave-button\n  .label = Save\n  .accesskey = S\n  .tooltiptext = Save the page\n",
+    );
+
+    let migration = Migrator::new()
+        .attributes(["label"])
+        .migrate(source, &Dtd)
+        .unwrap();
+    assert_ends_with(
+        &migration.fluent,
+        "save-button-accesskey = S\nsave-button-tooltiptext = Save the page\n",
+    );
+}
+
+#[test]
+fn test_migrate_unique_ids() {
+    let first = Migrator::new()
+        .migrate("back.label = Back\nbackLabel = Back again\n", &Properties)
+        .unwrap();
+    assert_eq!(first.ids, ["back-label", "back-label-2"]);
+    assert_eq!(first.warnings.len(), 1);
+
+    let second = Migrator::new()
+        .reserve_ids(first.ids)
+        .migrate("<!ENTITY back.label \"Back\">\n", &Dtd)
+        .unwrap();
+    assert_eq!(second.ids, ["back-label-3"]);
+    assert_ends_with(&second.fluent, "back-label-3 = Back\n");
+}
+
+#[test]
+fn test_migrate_includes() {
+    let dir = TestDir::new("includes");
+    let brand_path = dir.write(
+        "browser/branding/official/locales/en-US/brand.dtd",
+        "<!ENTITY brandShortName \"Firefox\">\n<!ENTITY % extra SYSTEM \"extra.dtd\">\n%extra;\n",
+    );
+    let extra_path = dir.write(
+        "browser/branding/official/locales/en-US/extra.dtd",
+        "<!ENTITY appName \"Nightly\">\n",
+    );
+
+    let path = dir.write(
+        "about.dtd",
+        "<!ENTITY % brandDTD SYSTEM \"chrome://branding/locale/brand.dtd\">\n%brandDTD;\n<!ENTITY about \"About &appName; by &brandShortName;\">\n",
+    );
+
+    // Without the source root, the include can't be found.
+    let migration = Migrator::new().migrate_file(&path).unwrap();
+    assert!(migration.fluent.contains("about = About { -app-name }"));
+    assert_eq!(migration.includes[0].path, None);
+
+    let migration = Migrator::new()
+        .source_root(&*dir)
+        .migrate_file(&path)
+        .unwrap();
+    assert!(
+        migration
+            .fluent
+            .contains("about = About Nightly by { -brand-short-name }\n"),
+        "{}",
+        migration.fluent
+    );
+    assert_eq!(migration.warnings, []);
+    assert_eq!(
+        migration.includes,
+        [IncludedDtd {
+            url: "chrome://branding/locale/brand.dtd".into(),
+            path: Some(brand_path),
+            includes: vec![IncludedDtd {
+                url: "extra.dtd".into(),
+                path: Some(extra_path),
+                includes: vec![],
+            }],
+        }]
+    );
+}
+
+#[test]
+fn test_source_root_find() {
+    let dir = TestDir::new("find");
+    let paths = [
+        "browser/branding/aurora/locales/en-US/brand.dtd",
+        "browser/branding/official/locales/en-US/brand.dtd",
+        "browser/branding/official/content/brand.dtd",
+        "toolkit/locales/en-US/chrome/global/global.dtd",
+        "toolkit/locales/en-US/chrome/mozapps/global.dtd",
+        ".hg/store/global.dtd",
+    ];
+    for path in &paths {
+        dir.write(path, "");
+    }
+
+    let root = SourceRoot::new(&*dir);
+    assert_eq!(
+        root.find("chrome://branding/locale/brand.dtd"),
+        Some(dir.join(paths[0]))
+    );
+    assert_eq!(
+        root.find("chrome://global/locale/global.dtd"),
+        Some(dir.join(paths[3]))
+    );
+    assert_eq!(root.find("chrome://global/locale/missing.dtd"), None);
+}
+
+#[test]
+fn test_migrate_partial() {
+    let source = "<!ENTITY back \"Back\">\n<!UNKNOWN foo>\n";
+    assert!(matches!(
+        Migrator::new().migrate(source, &Dtd),
+        Err(Error::Parse { .. })
+    ));
+
+    let migration = Migrator::new()
+        .allow_partial(true)
+        .migrate(source, &Dtd)
+        .unwrap();
+    assert_eq!(migration.diagnostics.len(), 1);
+    assert!(migration.fluent.contains("back = Back\n"));
+    assert!(migration.fluent.contains("#   <!UNKNOWN foo>\n"));
+}
+
+#[test]
+fn test_migrate_and_save_file() {
+    let dir = TestDir::new("save");
+    let path = dir.write("browser.dtd", "<!ENTITY back \"Back\">\n");
+
+    let migrator = Migrator::new();
+    let migration = migrator.migrate_file(&path).unwrap();
+    let save_path = migrator.save(&path, &migration).unwrap();
+    assert_eq!(save_path, dir.join("browser.ftl"));
+    assert_eq!(fs::read_to_string(&save_path).unwrap(), migration.fluent);
+
+    // Saving again conflicts, unless overwriting is allowed.
+    assert!(matches!(
+        migrator.save(&path, &migration),
+        Err(Error::OutputConflict(_))
+    ));
+    Migrator::new()
+        .overwrite(true)
+        .save(&path, &migration)
+        .unwrap();
+}
+
+#[test]
+fn test_migrate_file_errors() {
+    let dir = TestDir::new("errors");
+    let text_path = dir.write("notes.txt", "Not a localization file");
+    assert!(matches!(
+        Migrator::new().migrate_file(&text_path),
+        Err(Error::UnsupportedExtension(_))
+    ));
+    assert!(matches!(
+        Migrator::new().migrate_file(dir.join("missing.dtd")),
+        Err(Error::Io { .. })
+    ));
+
+    let broken_path = dir.write("broken.properties", "= No key\n");
+    match Migrator::new().migrate_file(&broken_path) {
+        Err(Error::Parse { path, .. }) => assert_eq!(path, Some(broken_path)),
+        result => panic!("Expected a parse error, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_migrate_selected_keys() {
+    let dir = TestDir::new("select");
+    let path = dir.write(
+        "downloads.properties",
+        "# License\n\n# LOCALIZATION NOTE (downloads.title): The window title.\ndownloads.title = Downloads\nhistory.title = History\ndownloads.clear = Clear\n",
+    );
+
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("downloads.title"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_eq!(migration.migrated_keys, ["downloads.title"]);
+    assert!(migration
+        .fluent
+        .contains("# The window title.\ndownloads-title = Downloads\n"));
+    assert!(!migration.fluent.contains("history"));
+    migrator.save(&path, &migration).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# License\n\nhistory.title = History\ndownloads.clear = Clear\n"
+    );
+
+    // The next migration is appended to the .ftl file.
+    let migrator = Migrator::new().select_keys(KeySelection::new().prefix("downloads."));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_eq!(migration.migrated_keys, ["downloads.clear"]);
+    assert_eq!(migration.messages, "downloads-clear = Clear\n");
+    let save_path = migrator.save(&path, &migration).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# License\n\nhistory.title = History\n"
+    );
+    let fluent = fs::read_to_string(&save_path).unwrap();
+    assert_ends_with(
+        &fluent,
+        "downloads-title = Downloads\n\ndownloads-clear = Clear\n",
+    );
+    assert_eq!(fluent.matches("Mozilla Public").count(), 1);
+}
+
+#[test]
+fn test_migrate_selected_keys_unique_ids() {
+    let dir = TestDir::new("select_unique");
+    let path = dir.write(
+        "downloads.properties",
+        "downloads.title = Downloads\ndownloadsTitle = Downloads again\n",
+    );
+
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("downloads.title"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_eq!(migration.ids, ["downloads-title"]);
+    migrator.save(&path, &migration).unwrap();
+
+    // The ID is already in the .ftl file that the next migration is appended to.
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("downloadsTitle"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_eq!(migration.ids, ["downloads-title-2"]);
+    assert_eq!(migration.warnings.len(), 1);
+    let save_path = migrator.save(&path, &migration).unwrap();
+    let fluent = fs::read_to_string(&save_path).unwrap();
+    assert_ends_with(
+        &fluent,
+        "downloads-title = Downloads\n\ndownloads-title-2 = Downloads again\n",
+    );
+}
+
+#[test]
+fn test_migrate_selected_attached_comments() {
+    let dir = TestDir::new("select_attached");
+    let path = dir.write(
+        "menu.dtd",
+        "<!-- License -->\n\n<!-- The Open menu item. -->\n<!ENTITY open.label \"Open\">\n<!-- The Close menu item. -->\n<!ENTITY close.label \"Close\">\n",
+    );
+
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("open.label"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_ends_with(
+        &migration.fluent,
+        "# The Open menu item.\nopen-label = Open\n",
+    );
+    assert!(!migration.fluent.contains("Close"));
+    migrator.save(&path, &migration).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "<!-- License -->\n\n<!-- The Close menu item. -->\n<!ENTITY close.label \"Close\">\n"
+    );
+}
+
+#[test]
+fn test_migrate_latin1_file() {
+    let dir = TestDir::new("latin1");
+    let path = dir.write("cafe.properties", b"cafe = Caf\xE9\r\nbar = Bar\r\n");
+
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("cafe"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_ends_with(&migration.fluent, "cafe = Café\n");
+    assert_eq!(migration.diagnostics, []);
+    assert_eq!(migration.warnings.len(), 1);
+
+    // The rest of the file keeps its encoding and line endings.
+    migrator.save(&path, &migration).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"bar = Bar\r\n");
+}
+
+#[test]
+fn test_migrate_mixed_line_endings() {
+    let dir = TestDir::new("mixed-line-endings");
+    let source = "<!-- License -->\r\n\r\n<!ENTITY back.label \"Back\">\r\n<!ENTITY forward.label \"Forward\">\n<!ENTITY stop.label\r  \"Stop\">\r\n\n<!ENTITY reload.label \"Reload\">\n";
+    let path = dir.write("browser.dtd", source);
+
+    // Nothing is removed without a selected key, so the file stays the same.
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("missing"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_eq!(migration.remaining_source.as_deref(), Some(source));
+
+    // Each remaining line keeps its own line ending.
+    let migrator = Migrator::new().select_keys(KeySelection::new().key("forward.label"));
+    let migration = migrator.migrate_file(&path).unwrap();
+    migrator.save(&path, &migration).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "<!-- License -->\r\n\r\n<!ENTITY back.label \"Back\">\r\n<!ENTITY stop.label\r  \"Stop\">\r\n\n<!ENTITY reload.label \"Reload\">\n"
+    );
+}
+
+/// A format with one `key -> value` message per line.
+struct Arrows;
+
+impl SourceFormat for Arrows {
+    fn name(&self) -> &'static str {
+        "arrows"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["arrows"]
+    }
+
+    fn sniff(&self, source: &str) -> bool {
+        source.contains(" -> ")
+    }
+
+    fn parse<'a>(&self, source: &'a str) -> (Vec<Node<'a>>, Vec<Diagnostic>) {
+        let mut nodes = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut offset = 0;
+        for line in source.split_inclusive('\n') {
+            match line.trim_end().split_once(" -> ") {
+                Some((key, value)) => nodes.push(Node::Message(Message {
+                    key: key.into(),
+                    value: value.into(),
+                    span: Span::new(source, line.trim_end()),
+                    key_span: Span::new(source, key),
+                })),
+                None => diagnostics.push(Diagnostic::new(source, offset, "No arrow".into())),
+            }
+            offset += line.len();
+        }
+        (nodes, diagnostics)
+    }
+
+    /// The placeholders are numbered in braces, like `{1}`.
+    fn parse_placeholders(&self, pattern: &Pattern) -> Pattern {
+        let mut parsed = Pattern::default();
+        for element in &pattern.elements {
+            let mut rest = match element {
+                PatternElement::Text(text) => text.as_str(),
+                element => {
+                    parsed.push(element.clone());
+                    continue;
+                }
+            };
+            while let Some(start) = rest.find('{') {
+                let source = rest[start..]
+                    .find('}')
+                    .map(|end| &rest[start..=start + end]);
+                match source
+                    .and_then(|source| Some((source, source[1..source.len() - 1].parse().ok()?)))
+                {
+                    Some((source, position)) => {
+                        parsed.push_text(&rest[..start]);
+                        parsed.push(PatternElement::Placeholder(Placeholder {
+                            position,
+                            source: source.into(),
+                        }));
+                        rest = &rest[start + source.len()..];
+                    }
+                    None => {
+                        parsed.push_text(&rest[..=start]);
+                        rest = &rest[start + 1..];
+                    }
+                }
+            }
+            parsed.push_text(rest);
+        }
+        parsed
+    }
+}
+
+#[test]
+fn test_register_format() {
+    let dir = TestDir::new("register");
+    let path = dir.write("strings.arrows", "back.label -> Back\n");
+
+    assert!(matches!(
+        Migrator::new().migrate_file(&path),
+        Err(Error::UnsupportedExtension(_))
+    ));
+    let migrator = Migrator::new().register_format(Arrows);
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_ends_with(&migration.fluent, "back-label = Back\n");
+
+    // The format is sniffed from the contents for unknown extensions.
+    let path = dir.write("strings.txt", "back.label -> Back\n");
+    let migration = migrator.migrate_file(&path).unwrap();
+    assert_ends_with(&migration.fluent, "back-label = Back\n");
+
+    // The placeholders are parsed with the syntax of the format.
+    let migration = migrator
+        .fallback_variable("name")
+        .migrate("open -> Open {1} {not} %S\n", &Arrows)
+        .unwrap();
+    assert_ends_with(
+        &migration.fluent,
+        "open = Open { $name } { \"{\" }not{ \"}\" } %S\n",
+    );
+}
+
+#[test]
+fn test_input_format() {
+    let dir = TestDir::new("input-format");
+    let path = dir.write("browser.inc", "back.label = Back\n");
+
+    let migration = Migrator::new()
+        .input_format("properties")
+        .migrate_file(&path)
+        .unwrap();
+    assert_ends_with(&migration.fluent, "back-label = Back\n");
+
+    // The chosen format overrides the extension.
+    let path = dir.write("browser.dtd", "back.label = Back\n");
+    assert!(Migrator::new().migrate_file(&path).is_err());
+    let migration = Migrator::new()
+        .input_format("properties")
+        .migrate_file(&path)
+        .unwrap();
+    assert_ends_with(&migration.fluent, "back-label = Back\n");
+
+    assert!(matches!(
+        Migrator::new().input_format("xliff").migrate_file(&path),
+        Err(Error::UnknownFormat(name)) if name == "xliff"
+    ));
+}